@@ -1,37 +1,307 @@
-use axum::{extract::Json as ExtractJson, http::StatusCode, response::Json};
-use cairo_runner_types::{CairoRunRequest, CairoRunResponse};
-use cairo_runners::{main_runner::run_cairo_code, test_runner::run_cairo_tests};
+use axum::{
+    extract::{Json as ExtractJson, Path},
+    http::StatusCode,
+    response::Json,
+};
+use cairo_runner_types::{CairoRunRequest, CairoRunResponse, RunOutcome, Session};
+use cairo_runners::{
+    compiler::compile,
+    error::RunnerError,
+    formatter::{format_cairo_code, FormatConfig},
+    main_runner::{
+        list_functions, max_steps, run_cairo_code, run_with_timeout, CairoProject, RunConfig,
+    },
+    sessions::{
+        call, create_session, declare, deploy, end_session, get_session, invoke, read_storage,
+    },
+    test_runner::{run_cairo_tests, TestsConfig},
+};
 
-pub async fn run_handler(
-    ExtractJson(request): ExtractJson<CairoRunRequest>,
-) -> Result<Json<CairoRunResponse>, StatusCode> {
-    let response = match run_cairo_code(request.code) {
-        Ok(message) => CairoRunResponse {
-            message,
-            success: true,
-        },
-        Err(message) => CairoRunResponse {
-            message: format!("{}", message),
-            success: false,
+/// Builds the response for a failed run, keeping the diagnostics of compilation errors.
+fn error_response(error: RunnerError) -> (StatusCode, Json<CairoRunResponse>) {
+    let status =
+        StatusCode::from_u16(error.http_status()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    let response = CairoRunResponse {
+        message: format!("{}", error),
+        success: false,
+        diagnostics: match error {
+            RunnerError::Compilation(e) => e.diagnostics,
+            _ => vec![],
         },
+        ..Default::default()
     };
+    (status, Json(response))
+}
+
+/// Builds the response of a request from the result of its run.
+fn response(result: Result<CairoRunResponse, RunnerError>) -> (StatusCode, Json<CairoRunResponse>) {
+    match result {
+        Ok(response) => (StatusCode::OK, Json(response)),
+        Err(error) => error_response(error),
+    }
+}
 
-    Ok(Json(response))
+/// Collects the crate sources of a request.
+fn request_project(request: CairoRunRequest) -> CairoProject {
+    CairoProject::new(request.code, request.files.unwrap_or_default())
+}
+
+pub async fn run_handler(
+    ExtractJson(request): ExtractJson<CairoRunRequest>,
+) -> (StatusCode, Json<CairoRunResponse>) {
+    let config = RunConfig::from_request(&request);
+    let project = request_project(request);
+    let result = run_with_timeout(move || {
+        let mut outcome = run_cairo_code(project, &config)?;
+        Ok(CairoRunResponse {
+            message: outcome.to_string(),
+            success: true,
+            diagnostics: std::mem::take(&mut outcome.diagnostics),
+            stdout: std::mem::take(&mut outcome.stdout),
+            outcome: Some(outcome),
+            ..Default::default()
+        })
+    })
+    .await;
+    response(result)
 }
 
 pub async fn test_handler(
     ExtractJson(request): ExtractJson<CairoRunRequest>,
-) -> Result<Json<CairoRunResponse>, StatusCode> {
-    let response = match run_cairo_tests(request.code.to_string()) {
-        Ok(message) => CairoRunResponse {
-            message: format!("{}", message.notes()),
+) -> (StatusCode, Json<CairoRunResponse>) {
+    let config = TestsConfig::from_request(&request);
+    let project = request_project(request);
+    let result = run_with_timeout(move || {
+        let summary = run_cairo_tests(project, &config)?;
+        Ok(CairoRunResponse {
+            message: summary.notes().to_string(),
             success: true,
-        },
-        Err(message) => CairoRunResponse {
-            message: format!("{}", message),
-            success: false,
-        },
+            diagnostics: summary.diagnostics().to_vec(),
+            stdout: summary.stdout(),
+            tests: Some(summary.results().to_vec()),
+            ..Default::default()
+        })
+    })
+    .await;
+    response(result)
+}
+
+pub async fn functions_handler(
+    ExtractJson(request): ExtractJson<CairoRunRequest>,
+) -> (StatusCode, Json<CairoRunResponse>) {
+    let project = request_project(request);
+    let result = run_with_timeout(move || {
+        let functions = list_functions(project)?;
+        Ok(CairoRunResponse {
+            message: format!("Found {} functions.", functions.len()),
+            success: true,
+            functions: Some(functions),
+            ..Default::default()
+        })
+    })
+    .await;
+    response(result)
+}
+
+pub async fn compile_handler(
+    ExtractJson(mut request): ExtractJson<CairoRunRequest>,
+) -> (StatusCode, Json<CairoRunResponse>) {
+    let artifacts = request.artifacts.take().unwrap_or_default();
+    let project = request_project(request);
+    let result = run_with_timeout(move || {
+        let mut artifacts = compile(project, &artifacts)?;
+        Ok(CairoRunResponse {
+            message: "Compiled successfully.".to_string(),
+            success: true,
+            diagnostics: std::mem::take(&mut artifacts.diagnostics),
+            artifacts: Some(artifacts),
+            ..Default::default()
+        })
+    })
+    .await;
+    response(result)
+}
+
+pub async fn format_handler(
+    ExtractJson(request): ExtractJson<CairoRunRequest>,
+) -> (StatusCode, Json<CairoRunResponse>) {
+    let config = FormatConfig {
+        max_line_length: request.max_line_length,
+        tab_size: request.tab_size,
+        sort_module_level_items: request.sort_module_level_items,
+        merge_use_items: request.merge_use_items,
+        diff: request.diff.unwrap_or_default(),
     };
+    let result = run_with_timeout(move || {
+        let formatted = format_cairo_code(&request.code, &config)?;
+        Ok(CairoRunResponse {
+            message: if formatted.changed {
+                "Formatted the code.".to_string()
+            } else {
+                "The code is already formatted.".to_string()
+            },
+            success: true,
+            formatted: Some(formatted),
+            ..Default::default()
+        })
+    })
+    .await;
+    response(result)
+}
 
-    Ok(Json(response))
+/// A field a request must have, e.g. the `contract` of a session's `/deploy`.
+fn required(value: Option<String>, field: &str) -> Result<String, RunnerError> {
+    value.ok_or_else(|| RunnerError::RunnerSetup(format!("The request has no `{field}`.")))
+}
+
+/// Builds the response of a session request that ran Cairo code.
+fn session_response(mut outcome: RunOutcome, session: Session) -> CairoRunResponse {
+    CairoRunResponse {
+        message: outcome.to_string(),
+        success: true,
+        diagnostics: std::mem::take(&mut outcome.diagnostics),
+        stdout: std::mem::take(&mut outcome.stdout),
+        outcome: Some(outcome),
+        session: Some(session),
+        ..Default::default()
+    }
+}
+
+/// Compiles the code of the request into a new session.
+pub async fn create_session_handler(
+    ExtractJson(request): ExtractJson<CairoRunRequest>,
+) -> (StatusCode, Json<CairoRunResponse>) {
+    let project = request_project(request);
+    let result = run_with_timeout(move || {
+        let session = create_session(project)?;
+        Ok(CairoRunResponse {
+            message: format!("Created session {}.", session.id),
+            success: true,
+            session: Some(session),
+            ..Default::default()
+        })
+    })
+    .await;
+    response(result)
+}
+
+pub async fn get_session_handler(Path(id): Path<String>) -> (StatusCode, Json<CairoRunResponse>) {
+    match get_session(&id) {
+        Ok(session) => (
+            StatusCode::OK,
+            Json(CairoRunResponse {
+                message: format!("Session {id} is live."),
+                success: true,
+                session: Some(session),
+                ..Default::default()
+            }),
+        ),
+        Err(error) => error_response(error),
+    }
+}
+
+pub async fn end_session_handler(Path(id): Path<String>) -> (StatusCode, Json<CairoRunResponse>) {
+    match end_session(&id) {
+        Ok(()) => (
+            StatusCode::OK,
+            Json(CairoRunResponse {
+                message: format!("Ended session {id}."),
+                success: true,
+                ..Default::default()
+            }),
+        ),
+        Err(error) => error_response(error),
+    }
+}
+
+pub async fn declare_handler(
+    Path(id): Path<String>,
+    ExtractJson(request): ExtractJson<CairoRunRequest>,
+) -> (StatusCode, Json<CairoRunResponse>) {
+    let result = run_with_timeout(move || {
+        let contract = required(request.contract, "contract")?;
+        let session = declare(&id, &contract)?;
+        Ok(CairoRunResponse {
+            message: format!("Declared `{contract}`."),
+            success: true,
+            session: Some(session),
+            ..Default::default()
+        })
+    })
+    .await;
+    response(result)
+}
+
+pub async fn deploy_handler(
+    Path(id): Path<String>,
+    ExtractJson(request): ExtractJson<CairoRunRequest>,
+) -> (StatusCode, Json<CairoRunResponse>) {
+    let result = run_with_timeout(move || {
+        let contract = required(request.contract, "contract")?;
+        let (outcome, session) = deploy(
+            &id,
+            &contract,
+            request.calldata.unwrap_or_default(),
+            request.salt.as_deref(),
+            Some(max_steps()),
+        )?;
+        Ok(session_response(outcome, session))
+    })
+    .await;
+    response(result)
+}
+
+pub async fn invoke_handler(
+    Path(id): Path<String>,
+    ExtractJson(request): ExtractJson<CairoRunRequest>,
+) -> (StatusCode, Json<CairoRunResponse>) {
+    let result = run_with_timeout(move || {
+        let contract_address = required(request.contract_address, "contract_address")?;
+        let entrypoint = required(request.entrypoint, "entrypoint")?;
+        let (outcome, session) = invoke(
+            &id,
+            &contract_address,
+            &entrypoint,
+            request.calldata.unwrap_or_default(),
+            Some(max_steps()),
+        )?;
+        Ok(session_response(outcome, session))
+    })
+    .await;
+    response(result)
+}
+
+pub async fn call_handler(
+    Path(id): Path<String>,
+    ExtractJson(request): ExtractJson<CairoRunRequest>,
+) -> (StatusCode, Json<CairoRunResponse>) {
+    let result = run_with_timeout(move || {
+        let contract_address = required(request.contract_address, "contract_address")?;
+        let entrypoint = required(request.entrypoint, "entrypoint")?;
+        let (outcome, session) = call(
+            &id,
+            &contract_address,
+            &entrypoint,
+            request.calldata.unwrap_or_default(),
+            Some(max_steps()),
+        )?;
+        Ok(session_response(outcome, session))
+    })
+    .await;
+    response(result)
+}
+
+pub async fn storage_handler(
+    Path(id): Path<String>,
+    ExtractJson(request): ExtractJson<CairoRunRequest>,
+) -> (StatusCode, Json<CairoRunResponse>) {
+    let result = run_with_timeout(move || {
+        let contract_address = required(request.contract_address, "contract_address")?;
+        let storage_key = required(request.storage_key, "storage_key")?;
+        let (outcome, session) =
+            read_storage(&id, &contract_address, &storage_key, Some(max_steps()))?;
+        Ok(session_response(outcome, session))
+    })
+    .await;
+    response(result)
 }
@@ -6,13 +6,24 @@ use tower::ServiceBuilder;
 use tower_http::{cors::CorsLayer, trace::TraceLayer};
 use tracing::info;
 
-use cairo_runner_api::{run_handler, test_handler};
+use cairo_runner_api::{
+    call_handler, compile_handler, create_session_handler, declare_handler, deploy_handler,
+    end_session_handler, format_handler, functions_handler, get_session_handler, invoke_handler,
+    run_handler, storage_handler, test_handler,
+};
+use cairo_runners::{
+    database::warm_up_databases, main_runner::program_cache_stats, test_runner::tests_cache_stats,
+};
 
 pub async fn health_handler() -> Json<serde_json::Value> {
     Json(serde_json::json!({
         "status": "healthy",
         "timestamp": chrono::Utc::now().to_rfc3339(),
-        "version": env!("CARGO_PKG_VERSION")
+        "version": env!("CARGO_PKG_VERSION"),
+        "compilation_cache": {
+            "programs": program_cache_stats(),
+            "tests": tests_cache_stats(),
+        }
     }))
 }
 
@@ -25,10 +36,26 @@ async fn main() -> anyhow::Result<()> {
         env!("CARGO_PKG_VERSION")
     );
 
+    // Analyze the corelib once before serving, instead of in the first requests
+    warm_up_databases()?;
+
     // Build the application with middleware
     let app = Router::new()
         .route("/run", post(run_handler))
         .route("/test", post(test_handler))
+        .route("/functions", post(functions_handler))
+        .route("/compile", post(compile_handler))
+        .route("/format", post(format_handler))
+        .route("/sessions", post(create_session_handler))
+        .route(
+            "/sessions/{id}",
+            get(get_session_handler).delete(end_session_handler),
+        )
+        .route("/sessions/{id}/declare", post(declare_handler))
+        .route("/sessions/{id}/deploy", post(deploy_handler))
+        .route("/sessions/{id}/invoke", post(invoke_handler))
+        .route("/sessions/{id}/call", post(call_handler))
+        .route("/sessions/{id}/storage", post(storage_handler))
         .route("/health", get(health_handler))
         .layer(
             ServiceBuilder::new()
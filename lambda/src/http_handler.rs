@@ -1,21 +1,53 @@
-use std::panic;
-
-use cairo_runner_types::CairoRunRequest;
-use cairo_runners::{main_runner::run_cairo_code, test_runner::run_cairo_tests};
+use cairo_runner_types::{CairoRunRequest, CairoRunResponse};
+use cairo_runners::{
+    error::RunnerError,
+    main_runner::{run_cairo_code, run_with_timeout, CairoProject, RunConfig},
+    test_runner::{run_cairo_tests, TestsConfig},
+};
 use lambda_http::{Body, Error, Request, Response};
 
-fn run_cairo_tests_get_notes(code: String) -> String {
-    match run_cairo_tests(code) {
-        Ok(res) => res.notes().to_string(),
-        Err(e) => format!("{e}"),
-    }
+fn error_response(e: RunnerError) -> (u16, CairoRunResponse) {
+    let status = e.http_status();
+    let response = CairoRunResponse {
+        message: format!("{e}"),
+        success: false,
+        diagnostics: match e {
+            RunnerError::Compilation(e) => e.diagnostics,
+            _ => vec![],
+        },
+        ..Default::default()
+    };
+    (status, response)
 }
 
-fn run_cairo_main_get_notes(code: String) -> String {
-    match run_cairo_code(code) {
-        Ok(res) => res.to_string(),
-        Err(e) => format!("{e}"),
-    }
+fn run_cairo_tests_response(
+    project: CairoProject,
+    config: &TestsConfig,
+) -> Result<CairoRunResponse, RunnerError> {
+    let res = run_cairo_tests(project, config)?;
+    Ok(CairoRunResponse {
+        message: res.notes().to_string(),
+        success: true,
+        diagnostics: res.diagnostics().to_vec(),
+        stdout: res.stdout(),
+        tests: Some(res.results().to_vec()),
+        ..Default::default()
+    })
+}
+
+fn run_cairo_main_response(
+    project: CairoProject,
+    config: &RunConfig,
+) -> Result<CairoRunResponse, RunnerError> {
+    let mut outcome = run_cairo_code(project, config)?;
+    Ok(CairoRunResponse {
+        message: outcome.to_string(),
+        success: true,
+        diagnostics: std::mem::take(&mut outcome.diagnostics),
+        stdout: std::mem::take(&mut outcome.stdout),
+        outcome: Some(outcome),
+        ..Default::default()
+    })
 }
 
 /// This is the main body for the function.
@@ -27,36 +59,33 @@ pub(crate) async fn function_handler(event: Request) -> Result<Response<Body>, E
     let request_data: CairoRunRequest = match body {
         Body::Text(text) => serde_json::from_str(text)?,
         Body::Binary(bytes) => serde_json::from_slice(bytes)?,
-        Body::Empty => CairoRunRequest {
-            code: String::new(),
-            test: None,
-        },
+        Body::Empty => CairoRunRequest::default(),
     };
     // Extract some useful information from the request
-    let code = request_data.code;
+    let config = RunConfig::from_request(&request_data);
+    let tests_config = TestsConfig::from_request(&request_data);
+    let project = CairoProject::new(request_data.code, request_data.files.unwrap_or_default());
 
-    let result = match request_data.test {
+    let run = run_with_timeout(move || match request_data.test {
         // run tests if the `test` query parameter is present
         Some(should_test) => match should_test {
-            true => {
-                let result = panic::catch_unwind(|| run_cairo_tests_get_notes(code.to_string()));
-                match result {
-                    Ok(notes) => notes,
-                    Err(e) => format!("Panic occurred: {:?}", e),
-                }
-            }
-            false => run_cairo_main_get_notes(code.to_string()),
+            true => run_cairo_tests_response(project, &tests_config),
+            false => run_cairo_main_response(project, &config),
         },
         // otherwise run the main function
-        None => run_cairo_main_get_notes(code.to_string()),
+        None => run_cairo_main_response(project, &config),
+    });
+    let (status, result) = match run.await {
+        Ok(response) => (200, response),
+        Err(e) => error_response(e),
     };
 
     // Return something that implements IntoResponse.
     // It will be serialized to the right response event automatically by the runtime
     let resp = Response::builder()
-        .status(200)
-        .header("content-type", "text/json")
-        .body(result.into())
+        .status(status)
+        .header("content-type", "application/json")
+        .body(serde_json::to_string(&result)?.into())
         .map_err(Box::new)?;
     Ok(resp)
 }
@@ -64,15 +93,31 @@ pub(crate) async fn function_handler(event: Request) -> Result<Response<Body>, E
 #[cfg(test)]
 mod tests {
     use super::*;
+    use cairo_runners::database::{database_pool_size, DatabaseKind, PooledDatabase};
     use lambda_http::Request;
     use serde_json::json;
+    use std::sync::Once;
+
+    /// Fills the pool of test databases once, like the cold start of the lambda does, as the tests
+    /// run concurrently and building a database takes longer than a request may.
+    fn warm_up_tests_databases() {
+        static WARM_UP: Once = Once::new();
+        WARM_UP.call_once(|| {
+            let checkouts: Vec<_> = (0..database_pool_size())
+                .map(|_| std::thread::spawn(|| PooledDatabase::checkout(DatabaseKind::Tests)))
+                .collect();
+            for checkout in checkouts {
+                checkout.join().unwrap().unwrap();
+            }
+        });
+    }
 
     #[tokio::test]
     async fn test_empty_code() {
         let request = Request::default();
 
         let response = function_handler(request).await.unwrap();
-        assert_eq!(response.status(), 200);
+        assert_eq!(response.status(), 404);
 
         let body_bytes = response.body().to_vec();
         let body_string = String::from_utf8(body_bytes).unwrap();
@@ -99,10 +144,15 @@ mod tests {
         let body_string = String::from_utf8(body_bytes).unwrap();
 
         assert!(body_string.contains("Run completed successfully, returning"));
+
+        let body: serde_json::Value = serde_json::from_str(&body_string).unwrap();
+        assert_eq!(body["outcome"]["status"], "success");
+        assert_eq!(body["outcome"]["values"], json!(["37"]));
     }
 
     #[tokio::test]
     async fn test_test_runner_with_main() {
+        warm_up_tests_databases();
         let code = "fn main() -> felt252 {0x25}";
 
         let request = Request::new(
@@ -122,11 +172,12 @@ mod tests {
 
         println!("Response body: {}", body_string);
 
-        assert!(body_string.contains("Run completed successfully, returning"));
+        assert!(body_string.contains("running 0 tests"));
     }
 
     #[tokio::test]
     async fn test_test_runner() {
+        warm_up_tests_databases();
         let code = r#"
             #[test]
             fn test_pass() {assert(true, 'should pass');}
@@ -152,5 +203,205 @@ mod tests {
         assert!(body_string.contains("running 2 tests"));
         assert!(body_string.contains("test lib::test_pass ... ok"));
         assert!(body_string.contains("test lib::test_fail ... fail"));
+
+        let body: serde_json::Value = serde_json::from_str(&body_string).unwrap();
+        assert_eq!(body["tests"][0]["name"], "lib::test_pass");
+        assert_eq!(body["tests"][0]["status"], "ok");
+        assert_eq!(body["tests"][1]["name"], "lib::test_fail");
+        assert_eq!(body["tests"][1]["status"], "fail");
+    }
+
+    #[tokio::test]
+    async fn test_test_runner_filter() {
+        warm_up_tests_databases();
+        let code = r#"
+            #[test]
+            fn test_pass() {}
+            #[test]
+            #[ignore]
+            fn test_slow() {}
+        "#;
+
+        let request = Request::new(
+            json!({
+                "code": code,
+                "test": true,
+                "filter": "slow",
+                "include_ignored": true
+            })
+            .to_string()
+            .into(),
+        );
+
+        let response = function_handler(request).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+
+        assert_eq!(body["tests"].as_array().unwrap().len(), 1);
+        assert_eq!(body["tests"][0]["name"], "lib::test_slow");
+        assert_eq!(body["tests"][0]["status"], "ok");
+    }
+
+    #[tokio::test]
+    async fn test_test_runner_gas_enabled() {
+        warm_up_tests_databases();
+        let request = Request::new(
+            json!({
+                "code": "#[test]\nfn test_add() { assert(1_u32 + 2 == 3, 'wrong'); }",
+                "test": true,
+                "gas_enabled": true
+            })
+            .to_string()
+            .into(),
+        );
+
+        let response = function_handler(request).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+
+        assert_eq!(body["tests"][0]["status"], "ok");
+        assert!(body["tests"][0]["gas_usage"].as_i64().unwrap() > 0);
+        assert!(body["tests"][0]["resources"]["steps"].as_u64().unwrap() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_main_runner_profile() {
+        let request = Request::new(
+            json!({
+                "code": "fn main() -> u32 { 1 + 2 }",
+                "profile": "sierra"
+            })
+            .to_string()
+            .into(),
+        );
+
+        let response = function_handler(request).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+
+        let profile = &body["outcome"]["profile"];
+        assert_eq!(profile["functions"][0]["function"], "lib::main");
+        assert!(profile["collapsed_stacks"]
+            .as_str()
+            .unwrap()
+            .starts_with("lib::main "));
+    }
+
+    #[tokio::test]
+    async fn test_main_runner_trace() {
+        let request = Request::new(
+            json!({
+                "code": "fn main() -> u32 { 1 + 2 }",
+                "trace": "json"
+            })
+            .to_string()
+            .into(),
+        );
+
+        let response = function_handler(request).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+
+        let trace = &body["outcome"]["trace"];
+        assert_eq!(trace["format"], "json");
+        assert!(trace["trace"][0]["pc"].is_u64());
+        assert!(!trace["memory"].as_array().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_main_runner_files() {
+        let request = Request::new(
+            json!({
+                "code": "mod utils;\nfn main() -> felt252 { utils::value() }",
+                "files": { "utils.cairo": "pub fn value() -> felt252 { 5 }" }
+            })
+            .to_string()
+            .into(),
+        );
+
+        let response = function_handler(request).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+
+        assert_eq!(body["outcome"]["values"], json!(["5"]));
+    }
+
+    #[tokio::test]
+    async fn test_main_runner_args() {
+        let request = Request::new(
+            json!({
+                "code": "fn main(a: u256, b: Array<felt252>) -> u256 { a + b.len().into() }",
+                "args": ["0x10", [1, 2, 3]]
+            })
+            .to_string()
+            .into(),
+        );
+
+        let response = function_handler(request).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+
+        assert_eq!(body["outcome"]["values"], json!(["19", "0"]));
+        assert_eq!(body["outcome"]["value"], json!("19"));
+    }
+
+    #[tokio::test]
+    async fn test_main_runner_entrypoint() {
+        let request = Request::new(
+            json!({
+                "code": "fn main() {}\nfn double(x: felt252) -> felt252 { x * 2 }",
+                "entrypoint": "::double",
+                "args": [21]
+            })
+            .to_string()
+            .into(),
+        );
+
+        let response = function_handler(request).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+
+        assert_eq!(body["outcome"]["values"], json!(["42"]));
+    }
+
+    #[tokio::test]
+    async fn test_main_runner_out_of_gas() {
+        let request = Request::new(
+            json!({
+                "code": "fn main() { loop {} }",
+                "available_gas": 100000
+            })
+            .to_string()
+            .into(),
+        );
+
+        let response = function_handler(request).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+
+        assert_eq!(body["success"], true);
+        assert_eq!(body["outcome"]["status"], "out_of_gas");
+    }
+
+    #[tokio::test]
+    async fn test_main_runner_stdout() {
+        let request = Request::new(
+            json!({ "code": "fn main() { println!(\"hello\"); }" })
+                .to_string()
+                .into(),
+        );
+
+        let response = function_handler(request).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+
+        assert_eq!(body["stdout"], "hello\n");
+    }
+
+    #[tokio::test]
+    async fn test_compile_error_diagnostics() {
+        let code = "fn main() -> felt252 {\n    4 + 5;\n}";
+
+        let request = Request::new(json!({ "code": code }).to_string().into());
+
+        let response = function_handler(request).await.unwrap();
+        assert_eq!(response.status(), 422);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+
+        assert_eq!(body["success"], false);
+        assert_eq!(body["diagnostics"][0]["severity"], "error");
+        assert_eq!(body["diagnostics"][0]["file"], "lib.cairo");
+        assert_eq!(body["diagnostics"][0]["start"], json!([0, 13]));
     }
 }
@@ -9,6 +9,9 @@ async fn main() -> Result<(), Error> {
     // required to enable CloudWatch error logging by the runtime
     tracing::init_default_subscriber();
 
+    // Analyze the corelib during the cold start, instead of in the first request
+    cairo_runners::database::warm_up_databases()?;
+
     // Define a layer to inject CORS headers
     let cors_layer = CorsLayer::new()
         .allow_methods(vec![Method::GET, Method::POST])
@@ -0,0 +1,51 @@
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+/// Generates `corelib.rs`, which embeds the sources of the bundled corelib and its version.
+fn main() {
+    let manifest_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
+    let corelib_dir = manifest_dir.join("../corelib").canonicalize().unwrap();
+    println!("cargo:rerun-if-changed={}", corelib_dir.display());
+
+    let manifest = std::fs::read_to_string(corelib_dir.join("Scarb.toml")).unwrap();
+    let version = manifest
+        .lines()
+        .find_map(|line| line.strip_prefix("version = "))
+        .expect("The corelib manifest has no version.")
+        .trim_matches('"');
+
+    let mut files = vec![];
+    let src_dir = corelib_dir.join("src");
+    collect_files(&src_dir, &src_dir, &mut files);
+    files.sort();
+
+    let mut output = format!("pub const EMBEDDED_CORELIB_VERSION: &str = {version:?};\n\n");
+    output += "pub const EMBEDDED_CORELIB_FILES: &[(&str, &str)] = &[\n";
+    for (path, full_path) in files {
+        writeln!(output, "    ({path:?}, include_str!({full_path:?})),").unwrap();
+    }
+    output += "];\n";
+
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR").unwrap());
+    std::fs::write(out_dir.join("corelib.rs"), output).unwrap();
+}
+
+/// Collects the `.cairo` files under `dir`, as their `/`-separated path relative to `src_dir` and
+/// their full path.
+fn collect_files(src_dir: &Path, dir: &Path, files: &mut Vec<(String, String)>) {
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        println!("cargo:rerun-if-changed={}", path.display());
+        if path.is_dir() {
+            collect_files(src_dir, &path, files);
+        } else if path.extension().is_some_and(|e| e == "cairo") {
+            let components: Vec<_> = path
+                .strip_prefix(src_dir)
+                .unwrap()
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect();
+            files.push((components.join("/"), path.display().to_string()));
+        }
+    }
+}
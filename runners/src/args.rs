@@ -0,0 +1,320 @@
+use anyhow::{anyhow, bail, ensure};
+use cairo_lang_runner::Arg;
+use cairo_lang_sierra::{
+    extensions::{
+        ConcreteType, NamedType,
+        bitwise::BitwiseType,
+        circuit::{AddModType, MulModType},
+        core::{CoreLibfunc, CoreType, CoreTypeConcrete},
+        ec::EcOpType,
+        gas::GasBuiltinType,
+        pedersen::PedersenType,
+        poseidon::PoseidonType,
+        range_check::{RangeCheck96Type, RangeCheckType},
+        segment_arena::SegmentArenaType,
+        starknet::syscalls::SystemType,
+        types::TypeInfo,
+        utils::Range,
+    },
+    ids::{ConcreteTypeId, GenericTypeId},
+    program::{Function, GenericArg, Program},
+    program_registry::ProgramRegistry,
+};
+use cairo_lang_utils::byte_array::BYTES_IN_WORD;
+use cairo_runner_types::FunctionSignature;
+use num_bigint::{BigInt, BigUint, Sign};
+use serde_json::Value;
+use starknet_types_core::felt::Felt as Felt252;
+
+/// Serializes JSON arguments into the runner's arguments, grouped per parameter, following the
+/// parameter types of `func`. Implicit parameters (builtins, gas, syscalls) are skipped.
+///
+/// Every argument is either a felt-like scalar (a JSON number, or a decimal or `0x` hex string),
+/// or, depending on its type:
+/// - `bool`: a JSON boolean.
+/// - `u256`: a scalar, split into its low and high words.
+/// - `ByteArray`: a JSON string.
+/// - `Array<T>` and `Span<T>`: a JSON array of `T`s.
+/// - any other struct: a JSON array of its members, in declaration order.
+///
+/// Integer scalars must be within the range of their type.
+pub fn encode_args(
+    program: &Program,
+    func: &Function,
+    args: &[Value],
+) -> anyhow::Result<Vec<Vec<Arg>>> {
+    let registry = ProgramRegistry::<CoreType, CoreLibfunc>::new(program)?;
+    let param_types: Vec<&ConcreteTypeId> = func
+        .signature
+        .param_types
+        .iter()
+        .filter(|ty| {
+            registry
+                .get_type(ty)
+                .is_ok_and(|ty| !is_implicit(&ty.info().long_id.generic_id))
+        })
+        .collect();
+    ensure!(
+        param_types.len() == args.len(),
+        "Expected {} arguments, got {}.",
+        param_types.len(),
+        args.len()
+    );
+
+    let mut encoded = vec![];
+    for (index, (ty, value)) in param_types.into_iter().zip(args).enumerate() {
+        let mut param = vec![];
+        encode_value(&registry, ty, value, &mut param)
+            .map_err(|e| anyhow!("Invalid argument {index}: {e}"))?;
+        encoded.push(param);
+    }
+    Ok(encoded)
+}
+
+/// Returns the user-facing signatures of the functions whose names start with `prefix`, skipping
+/// implicit parameters and unwrapping the panic wrapper of the return type.
+pub fn function_signatures(
+    program: &Program,
+    prefix: &str,
+) -> anyhow::Result<Vec<FunctionSignature>> {
+    let registry = ProgramRegistry::<CoreType, CoreLibfunc>::new(program)?;
+    let mut signatures = vec![];
+    for func in &program.funcs {
+        let name = type_or_function_name(func.id.debug_name.as_deref(), func.id.id);
+        if !name.starts_with(prefix) {
+            continue;
+        }
+        let (returns, panics) = user_return_types(&registry, func)?;
+        signatures.push(FunctionSignature {
+            name,
+            params: user_types(&registry, &func.signature.param_types)?
+                .iter()
+                .map(|ty| type_or_function_name(ty.debug_name.as_deref(), ty.id))
+                .collect(),
+            returns: returns
+                .iter()
+                .map(|ty| type_or_function_name(ty.debug_name.as_deref(), ty.id))
+                .collect(),
+            panics,
+        });
+    }
+    Ok(signatures)
+}
+
+/// The types of `types` that are passed by the user rather than implicitly by the runner.
+fn user_types(
+    registry: &ProgramRegistry<CoreType, CoreLibfunc>,
+    types: &[ConcreteTypeId],
+) -> anyhow::Result<Vec<ConcreteTypeId>> {
+    let mut user_types = vec![];
+    for ty in types {
+        if !is_implicit(&registry.get_type(ty)?.info().long_id.generic_id) {
+            user_types.push(ty.clone());
+        }
+    }
+    Ok(user_types)
+}
+
+/// The user-facing return types of `func`, unwrapped from its panic wrapper, and whether it has
+/// one.
+pub(crate) fn user_return_types(
+    registry: &ProgramRegistry<CoreType, CoreLibfunc>,
+    func: &Function,
+) -> anyhow::Result<(Vec<ConcreteTypeId>, bool)> {
+    let returns = user_types(registry, &func.signature.ret_types)?;
+    if let [ret] = &returns[..]
+        && let CoreTypeConcrete::Enum(enm) = registry.get_type(ret)?
+        && user_type_name(&enm.info).is_some_and(|n| n.starts_with("core::panics::PanicResult"))
+    {
+        // `PanicResult<(T,)>` - the value is the single member of the `Ok` variant.
+        let CoreTypeConcrete::Struct(tuple) = registry.get_type(&enm.variants[0])? else {
+            bail!("Unexpected panic wrapper of `{}`.", func.id);
+        };
+        return Ok((tuple.members.clone(), true));
+    }
+    Ok((returns, false))
+}
+
+/// The debug name of a Sierra id, or its numeric form if it has none.
+pub(crate) fn type_or_function_name(debug_name: Option<&str>, id: u64) -> String {
+    debug_name.map_or_else(|| format!("[{id}]"), str::to_string)
+}
+
+/// Returns whether the type is passed implicitly by the runner rather than by the user.
+fn is_implicit(generic_id: &GenericTypeId) -> bool {
+    [
+        AddModType::ID,
+        BitwiseType::ID,
+        GasBuiltinType::ID,
+        EcOpType::ID,
+        MulModType::ID,
+        PedersenType::ID,
+        PoseidonType::ID,
+        RangeCheck96Type::ID,
+        RangeCheckType::ID,
+        SegmentArenaType::ID,
+        SystemType::ID,
+    ]
+    .contains(generic_id)
+}
+
+/// The debug name of a user defined type, e.g. `core::integer::u256`.
+pub(crate) fn user_type_name(info: &TypeInfo) -> Option<&str> {
+    match info.long_id.generic_args.first() {
+        Some(GenericArg::UserType(id)) => id.debug_name.as_deref(),
+        _ => None,
+    }
+}
+
+fn encode_value(
+    registry: &ProgramRegistry<CoreType, CoreLibfunc>,
+    ty: &ConcreteTypeId,
+    value: &Value,
+    encoded: &mut Vec<Arg>,
+) -> anyhow::Result<()> {
+    match registry.get_type(ty)? {
+        CoreTypeConcrete::Array(array) => {
+            let Value::Array(items) = value else {
+                bail!("expected an array, got `{value}`.");
+            };
+            let mut elements = vec![];
+            for item in items {
+                encode_value(registry, &array.ty, item, &mut elements)?;
+            }
+            encoded.push(Arg::Array(elements));
+        }
+        CoreTypeConcrete::Snapshot(inner) => encode_value(registry, &inner.ty, value, encoded)?,
+        CoreTypeConcrete::NonZero(inner) => {
+            let start = encoded.len();
+            encode_value(registry, &inner.ty, value, encoded)?;
+            ensure!(
+                encoded[start..]
+                    .iter()
+                    .any(|arg| !matches!(arg, Arg::Value(v) if *v == Felt252::ZERO)),
+                "expected a non-zero value."
+            );
+        }
+        CoreTypeConcrete::Enum(enm) if user_type_name(&enm.info) == Some("core::bool") => {
+            let selector = match value {
+                Value::Bool(b) => *b as u64,
+                _ => match parse_scalar(value)? {
+                    n if n == BigInt::from(0) || n == BigInt::from(1) => n.try_into()?,
+                    _ => bail!("expected a boolean, got `{value}`."),
+                },
+            };
+            encoded.push(Arg::Value(selector.into()));
+        }
+        CoreTypeConcrete::Struct(strct) => match user_type_name(&strct.info) {
+            Some("core::integer::u256") if !value.is_array() => {
+                let Some(n) = parse_scalar(value)?.to_biguint() else {
+                    bail!("expected a non-negative u256, got `{value}`.");
+                };
+                ensure!(n.bits() <= 256, "`{value}` does not fit in a u256.");
+                let mask = (BigUint::from(1u8) << 128) - 1u8;
+                encoded.push(Arg::Value(Felt252::from(&n & &mask)));
+                encoded.push(Arg::Value(Felt252::from(n >> 128)));
+            }
+            Some("core::byte_array::ByteArray") if value.is_string() => {
+                encode_byte_array(value.as_str().unwrap(), encoded);
+            }
+            // Single member wrappers, such as `Span<T>`, take their member's value directly.
+            _ if strct.members.len() == 1 => {
+                encode_value(registry, &strct.members[0], value, encoded)?;
+            }
+            _ => {
+                let Value::Array(items) = value else {
+                    bail!(
+                        "expected an array of {} members, got `{value}`.",
+                        strct.members.len()
+                    );
+                };
+                ensure!(
+                    items.len() == strct.members.len(),
+                    "expected {} members, got {}.",
+                    strct.members.len(),
+                    items.len()
+                );
+                for (member, item) in strct.members.iter().zip(items) {
+                    encode_value(registry, member, item, encoded)?;
+                }
+            }
+        },
+        CoreTypeConcrete::Felt252(_) | CoreTypeConcrete::Starknet(_) => {
+            encoded.push(Arg::Value(parse_felt(value)?));
+        }
+        int @ (CoreTypeConcrete::Bytes31(_)
+        | CoreTypeConcrete::Uint8(_)
+        | CoreTypeConcrete::Uint16(_)
+        | CoreTypeConcrete::Uint32(_)
+        | CoreTypeConcrete::Uint64(_)
+        | CoreTypeConcrete::Uint128(_)
+        | CoreTypeConcrete::Sint8(_)
+        | CoreTypeConcrete::Sint16(_)
+        | CoreTypeConcrete::Sint32(_)
+        | CoreTypeConcrete::Sint64(_)
+        | CoreTypeConcrete::Sint128(_)
+        | CoreTypeConcrete::BoundedInt(_)) => {
+            let n = parse_scalar(value)?;
+            let range = Range::from_type_info(int.info())?;
+            ensure!(
+                range.lower <= n && n < range.upper,
+                "`{value}` is out of the range of `{}`, [{}, {}].",
+                int.info().long_id,
+                range.lower,
+                &range.upper - 1
+            );
+            encoded.push(Arg::Value(Felt252::from(&n)));
+        }
+        other => bail!(
+            "unsupported parameter type `{}`.",
+            other.info().long_id.generic_id
+        ),
+    }
+    Ok(())
+}
+
+/// Parses a felt like the `felt252` arguments, see [`parse_scalar`].
+pub(crate) fn parse_felt(value: &Value) -> anyhow::Result<Felt252> {
+    Ok(Felt252::from(&parse_scalar(value)?))
+}
+
+/// Parses a JSON number, or a decimal or `0x` prefixed hex string.
+fn parse_scalar(value: &Value) -> anyhow::Result<BigInt> {
+    let parsed = match value {
+        Value::Number(n) => n
+            .as_i64()
+            .map(BigInt::from)
+            .or_else(|| n.as_u64().map(BigInt::from)),
+        Value::String(s) => {
+            let (sign, digits) = match s.strip_prefix('-') {
+                Some(digits) => (Sign::Minus, digits),
+                None => (Sign::Plus, s.as_str()),
+            };
+            match digits.strip_prefix("0x") {
+                Some(hex) => BigUint::parse_bytes(hex.as_bytes(), 16),
+                None => BigUint::parse_bytes(digits.as_bytes(), 10),
+            }
+            .map(|n| BigInt::from_biguint(sign, n))
+        }
+        _ => None,
+    };
+    parsed.ok_or_else(|| anyhow!("expected a number, got `{value}`."))
+}
+
+/// Serializes a string the way `ByteArray` is laid out: its full 31 byte words, the pending word
+/// and the pending word's length.
+fn encode_byte_array(value: &str, encoded: &mut Vec<Arg>) {
+    let chunks: Vec<&[u8]> = value.as_bytes().chunks(BYTES_IN_WORD).collect();
+    let (pending, words) = match chunks.split_last() {
+        Some((last, words)) if last.len() < BYTES_IN_WORD => (*last, words),
+        _ => (&[][..], &chunks[..]),
+    };
+    encoded.push(Arg::Array(
+        words
+            .iter()
+            .map(|word| Arg::Value(Felt252::from_bytes_be_slice(word)))
+            .collect(),
+    ));
+    encoded.push(Arg::Value(Felt252::from_bytes_be_slice(pending)));
+    encoded.push(Arg::Value(pending.len().into()));
+}
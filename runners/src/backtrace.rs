@@ -0,0 +1,207 @@
+use std::collections::{HashMap, HashSet};
+
+use cairo_lang_defs::db::DefsGroup;
+use cairo_lang_runnable_utils::builder::RunnableBuilder;
+use cairo_lang_runner::profiling::user_function_idx_by_sierra_statement_idx;
+use cairo_lang_sierra::extensions::NamedLibfunc;
+use cairo_lang_sierra::extensions::core::{CoreConcreteLibfunc, CoreTypeConcrete};
+use cairo_lang_sierra::extensions::function_call::FunctionCallLibfunc;
+use cairo_lang_sierra::extensions::gas::GasConcreteLibfunc;
+use cairo_lang_sierra::ids::GenericLibfuncId;
+use cairo_lang_sierra::program::{BranchTarget, Program, Statement, StatementIdx};
+use cairo_lang_sierra_generator::statements_locations::{
+    StatementsLocations, maybe_code_location, maybe_containing_function_identifier,
+};
+use cairo_runner_types::{BacktraceFrame, SourceLocation};
+use cairo_vm::vm::trace::trace_entry::RelocatedTraceEntry;
+use starknet_types_core::felt::Felt as Felt252;
+
+use crate::args::{type_or_function_name, user_type_name};
+
+/// Finds where a run panicked from its VM trace: the `return` of the function that created the
+/// panic, followed by the `function_call`s of its callers, innermost first. Empty if the run
+/// didn't panic.
+///
+/// A panic is a `PanicResult::Err` returned up to the entrypoint, so the panicking function is the
+/// first one to return an `Err`.
+pub fn panic_statements(
+    builder: &RunnableBuilder,
+    trace: &[RelocatedTraceEntry],
+    memory: &[Option<Felt252>],
+) -> Vec<StatementIdx> {
+    let program = builder.sierra_program();
+    let mut calls = vec![];
+    for (idx, step) in trace_statements(builder, trace) {
+        match &program.statements[idx.0] {
+            Statement::Invocation(invocation) => {
+                if let Ok(CoreConcreteLibfunc::FunctionCall(_)) =
+                    builder.registry().get_libfunc(&invocation.libfunc_id)
+                {
+                    calls.push(idx);
+                }
+            }
+            Statement::Return(_) => {
+                if returns_err(builder, idx, step.ap, memory) {
+                    return [idx].into_iter().chain(calls.into_iter().rev()).collect();
+                }
+                calls.pop();
+            }
+        }
+    }
+    vec![]
+}
+
+/// Whether the last gas withdrawal of a run failed, from its VM trace. A metered function starts
+/// with a `withdraw_gas` whose failure branch panics, so this tells a run that ran out of gas from
+/// one whose code panicked with the same data.
+pub fn gas_withdrawal_failed(builder: &RunnableBuilder, trace: &[RelocatedTraceEntry]) -> bool {
+    let mut statements: Vec<_> = trace_statements(builder, trace)
+        .map(|(idx, _)| idx)
+        .collect();
+    statements.dedup();
+    // Statements without code, such as the `branch_align` starting a branch, share the offset of
+    // the next one, so the branch taken is told by the offset the run continues at.
+    let statement_info = &builder.casm_program().debug_info.sierra_statement_info;
+    let mut failed = false;
+    for pair in statements.windows(2) {
+        if let Some(failure_target) = withdrawal_failure_target(builder, pair[0]) {
+            failed = statement_info[pair[1].0].start_offset
+                == statement_info[failure_target.0].start_offset;
+        }
+    }
+    failed
+}
+
+/// The statement the gas withdrawal `idx` continues to when it fails, `None` if `idx` is not a
+/// gas withdrawal.
+fn withdrawal_failure_target(builder: &RunnableBuilder, idx: StatementIdx) -> Option<StatementIdx> {
+    let Statement::Invocation(invocation) = &builder.sierra_program().statements[idx.0] else {
+        return None;
+    };
+    match builder.registry().get_libfunc(&invocation.libfunc_id) {
+        Ok(CoreConcreteLibfunc::Gas(
+            GasConcreteLibfunc::WithdrawGas(_) | GasConcreteLibfunc::BuiltinWithdrawGas(_),
+        )) => match invocation.branches.get(1)?.target {
+            BranchTarget::Statement(target) => Some(target),
+            BranchTarget::Fallthrough => None,
+        },
+        _ => None,
+    }
+}
+
+/// The Sierra statement of each step of a VM trace, skipping the header and the footer around the
+/// program.
+fn trace_statements<'a>(
+    builder: &'a RunnableBuilder,
+    trace: &'a [RelocatedTraceEntry],
+) -> impl Iterator<Item = (StatementIdx, &'a RelocatedTraceEntry)> + 'a {
+    let statement_info = &builder.casm_program().debug_info.sierra_statement_info;
+    // The program comes right after the header, which ends with the `ret` of the run.
+    let real_pc_0 = trace.last().map_or(0, |last_step| last_step.pc + 1);
+    let program_end = statement_info.last().map_or(0, |info| info.end_offset);
+    trace.iter().filter_map(move |step| {
+        let pc = step
+            .pc
+            .checked_sub(real_pc_0)
+            .filter(|pc| *pc < program_end)?;
+        let idx = StatementIdx(statement_info.partition_point(|info| info.start_offset <= pc) - 1);
+        Some((idx, step))
+    })
+}
+
+/// Whether the `return` statement `idx`, about to run with `ap`, returns a `PanicResult::Err`.
+fn returns_err(
+    builder: &RunnableBuilder,
+    idx: StatementIdx,
+    ap: usize,
+    memory: &[Option<Felt252>],
+) -> bool {
+    let program = builder.sierra_program();
+    let func = &program.funcs[user_function_idx_by_sierra_statement_idx(program, idx)];
+    let Some(ret_ty) = func.signature.ret_types.last() else {
+        return false;
+    };
+    let Ok(CoreTypeConcrete::Enum(enm)) = builder.registry().get_type(ret_ty) else {
+        return false;
+    };
+    if !user_type_name(&enm.info).is_some_and(|n| n.starts_with("core::panics::PanicResult")) {
+        return false;
+    }
+    // The returned values end at `ap`, and the selector of a 2 variant enum is its index.
+    let size = builder.type_size(ret_ty) as usize;
+    ap.checked_sub(size)
+        .and_then(|selector| memory.get(selector).copied().flatten())
+        .is_some_and(|selector| selector == Felt252::ONE)
+}
+
+/// The backtrace frames of the statements that can be in [`panic_statements`], resolved from the
+/// debug info of the compilation, as the cached programs don't keep their database.
+#[derive(Debug, Default)]
+pub struct BacktraceMap {
+    frames: HashMap<StatementIdx, Vec<BacktraceFrame>>,
+}
+
+impl BacktraceMap {
+    pub fn new(db: &dyn DefsGroup, program: &Program, locations: &StatementsLocations) -> Self {
+        let function_calls: HashSet<_> = program
+            .libfunc_declarations
+            .iter()
+            .filter(|declaration| {
+                declaration.long_id.generic_id
+                    == GenericLibfuncId::from(FunctionCallLibfunc::STR_ID)
+            })
+            .map(|declaration| declaration.id.clone())
+            .collect();
+        let mut frames = HashMap::new();
+        for (idx, statement) in program.statements.iter().enumerate() {
+            let in_backtraces = match statement {
+                Statement::Invocation(invocation) => {
+                    function_calls.contains(&invocation.libfunc_id)
+                }
+                Statement::Return(_) => true,
+            };
+            if !in_backtraces {
+                continue;
+            }
+            let Some(statement_locations) = locations.locations.get(&StatementIdx(idx)) else {
+                continue;
+            };
+            // The locations of an inlined statement are followed by the calls it was inlined at.
+            let statement_frames = statement_locations
+                .iter()
+                .filter_map(|location| {
+                    let function = maybe_containing_function_identifier(db, *location)?;
+                    let location =
+                        maybe_code_location(db, *location).map(|(file, span, _)| SourceLocation {
+                            file: file.0,
+                            line: span.start.line,
+                            col: span.start.col,
+                        });
+                    Some(BacktraceFrame { function, location })
+                })
+                .collect();
+            frames.insert(StatementIdx(idx), statement_frames);
+        }
+        Self { frames }
+    }
+
+    /// The backtrace of [`panic_statements`] in `program`, innermost frame first.
+    pub fn backtrace(&self, program: &Program, statements: &[StatementIdx]) -> Vec<BacktraceFrame> {
+        let mut backtrace = vec![];
+        for idx in statements {
+            match self.frames.get(idx) {
+                Some(frames) if !frames.is_empty() => backtrace.extend(frames.iter().cloned()),
+                // Code generated by the compiler, e.g. a wrapper of an entrypoint.
+                _ => {
+                    let func =
+                        &program.funcs[user_function_idx_by_sierra_statement_idx(program, *idx)];
+                    backtrace.push(BacktraceFrame {
+                        function: type_or_function_name(func.id.debug_name.as_deref(), func.id.id),
+                        location: None,
+                    });
+                }
+            }
+        }
+        backtrace
+    }
+}
@@ -0,0 +1,166 @@
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use lru::LruCache;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::main_runner::CairoProject;
+
+/// The default number of compilations each cache keeps, see [`cache_capacity`].
+pub const DEFAULT_CACHE_CAPACITY: usize = 64;
+
+/// The number of compilations each cache keeps, from the `COMPILATION_CACHE_CAPACITY`
+/// environment variable or [`DEFAULT_CACHE_CAPACITY`]. A capacity of 0 disables caching.
+pub fn cache_capacity() -> usize {
+    std::env::var("COMPILATION_CACHE_CAPACITY")
+        .ok()
+        .and_then(|capacity| capacity.parse().ok())
+        .unwrap_or(DEFAULT_CACHE_CAPACITY)
+}
+
+/// The SHA-256 of the sources of a project and of what they are compiled for.
+pub type CacheKey = [u8; 32];
+
+/// Hashes the files of `project`, compiled as `kind` (e.g. `"program"` or `"tests"`).
+pub fn cache_key(kind: &str, project: &CairoProject) -> CacheKey {
+    let mut hasher = Sha256::new();
+    // Lengths prefix every field, so that moving bytes between paths and contents changes the key.
+    hasher.update((kind.len() as u64).to_le_bytes());
+    hasher.update(kind);
+    for (path, content) in &project.files {
+        hasher.update((path.len() as u64).to_le_bytes());
+        hasher.update(path);
+        hasher.update((content.len() as u64).to_le_bytes());
+        hasher.update(content);
+    }
+    hasher.finalize().into()
+}
+
+/// The usage of a [`CompilationCache`] since the server started.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+    pub capacity: usize,
+}
+
+/// A bounded cache of compilations, evicting the least recently used one when full.
+pub struct CompilationCache<V> {
+    entries: Mutex<Option<LruCache<CacheKey, Arc<V>>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<V> CompilationCache<V> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: Mutex::new(NonZeroUsize::new(capacity).map(LruCache::new)),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the cached compilation of `key`, or caches the one of `compile`. Failed
+    /// compilations are not cached, and the lock is not held while compiling, so concurrent
+    /// misses of the same key may both compile.
+    pub fn get_or_try_insert<E>(
+        &self,
+        key: CacheKey,
+        compile: impl FnOnce() -> Result<V, E>,
+    ) -> Result<Arc<V>, E> {
+        let cached = self
+            .entries
+            .lock()
+            .unwrap()
+            .as_mut()
+            .and_then(|entries| entries.get(&key).cloned());
+        if let Some(value) = cached {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(value);
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+
+        let value = Arc::new(compile()?);
+        if let Some(entries) = self.entries.lock().unwrap().as_mut() {
+            entries.put(key, value.clone());
+        }
+        Ok(value)
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let entries = self.entries.lock().unwrap();
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: entries.as_ref().map_or(0, LruCache::len),
+            capacity: entries.as_ref().map_or(0, |entries| entries.cap().get()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod cache_tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_key() {
+        let project = CairoProject::from("fn main() {}".to_string());
+        assert_eq!(
+            cache_key("program", &project),
+            cache_key("program", &project)
+        );
+        assert_ne!(cache_key("program", &project), cache_key("tests", &project));
+        assert_ne!(
+            cache_key("program", &project),
+            cache_key("program", &CairoProject::from("fn main() { }".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_cache_lru() {
+        let cache = CompilationCache::new(2);
+        let compile = |value: u32| move || Ok::<_, ()>(value);
+        assert_eq!(*cache.get_or_try_insert([1; 32], compile(1)).unwrap(), 1);
+        assert_eq!(*cache.get_or_try_insert([2; 32], compile(2)).unwrap(), 2);
+        assert_eq!(*cache.get_or_try_insert([1; 32], compile(10)).unwrap(), 1);
+        // Evicts `[2; 32]`, the least recently used.
+        assert_eq!(*cache.get_or_try_insert([3; 32], compile(3)).unwrap(), 3);
+        assert_eq!(*cache.get_or_try_insert([2; 32], compile(20)).unwrap(), 20);
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 1,
+                misses: 4,
+                entries: 2,
+                capacity: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn test_cache_failures_and_disabled() {
+        let cache = CompilationCache::new(1);
+        assert!(cache.get_or_try_insert([1; 32], || Err(())).is_err());
+        assert_eq!(
+            *cache.get_or_try_insert([1; 32], || Ok::<_, ()>(1)).unwrap(),
+            1
+        );
+        assert_eq!(cache.stats().misses, 2);
+
+        let cache = CompilationCache::new(0);
+        cache.get_or_try_insert([1; 32], || Ok::<_, ()>(1)).unwrap();
+        cache.get_or_try_insert([1; 32], || Ok::<_, ()>(1)).unwrap();
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 0,
+                misses: 2,
+                entries: 0,
+                capacity: 0,
+            }
+        );
+    }
+}
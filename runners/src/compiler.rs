@@ -0,0 +1,163 @@
+use anyhow::Context;
+use cairo_lang_compiler::{CompilerConfig, db::RootDatabase, diagnostics::DiagnosticsReporter};
+use cairo_lang_defs::ids::TopLevelLanguageElementId;
+use cairo_lang_runnable_utils::builder::RunnableBuilder;
+use cairo_lang_sierra::{
+    debug_info::DebugInfo,
+    program::{ProgramArtifact, VersionedProgram},
+};
+use cairo_lang_sierra_to_casm::metadata::MetadataComputationConfig;
+use cairo_lang_starknet::{compile::compile_prepared_db, contract::find_contracts};
+use cairo_lang_starknet_classes::casm_contract_class::CasmContractClass;
+use cairo_runner_types::{ArtifactKind, CompiledArtifacts, ContractArtifacts};
+
+use crate::diagnostics::CompilationError;
+use crate::error::RunnerError;
+use crate::main_runner::{CairoProject, CompiledProject, compile_project};
+
+/// Compiles a project without running it, building the requested `artifacts`. Fails with a
+/// [`RunnerError::Compilation`] if the code has errors or an artifact can't be built from it.
+pub fn compile(
+    project: impl Into<CairoProject>,
+    artifacts: &[ArtifactKind],
+) -> Result<CompiledArtifacts, RunnerError> {
+    let compiled = compile_project(&project.into())?;
+    let failed = |error: anyhow::Error| -> RunnerError {
+        CompilationError {
+            message: format!("{error:#}"),
+            diagnostics: compiled.diagnostics.clone(),
+        }
+        .into()
+    };
+    let program = &compiled.sierra_program;
+
+    let mut output = CompiledArtifacts::default();
+    for kind in artifacts {
+        match kind {
+            ArtifactKind::Sierra => output.sierra = Some(program.to_string()),
+            ArtifactKind::SierraJson => {
+                let artifact = ProgramArtifact::stripped(program.clone())
+                    .with_debug_info(DebugInfo::extract(program));
+                output.sierra_json = Some(
+                    serde_json::to_value(VersionedProgram::from(artifact))
+                        .map_err(|e| RunnerError::Internal(e.to_string()))?,
+                );
+            }
+            ArtifactKind::Casm => {
+                let builder = RunnableBuilder::new(
+                    program.clone(),
+                    Some(MetadataComputationConfig::default()),
+                )
+                .context("Failed to compile the program to CASM")
+                .map_err(failed)?;
+                output.casm = Some(builder.casm_program().to_string());
+            }
+            ArtifactKind::Contracts => {
+                output.contracts = Some(contract_artifacts(&compiled).map_err(failed)?);
+            }
+        }
+    }
+    output.diagnostics = compiled.diagnostics;
+    Ok(output)
+}
+
+/// Builds the contract class and compiled class of every contract of the project.
+fn contract_artifacts(compiled: &CompiledProject) -> anyhow::Result<Vec<ContractArtifacts>> {
+    let db: &RootDatabase = &compiled.db;
+    let contracts = find_contracts(db, &[compiled.crate_id]);
+    let contract_classes = compile_prepared_db(
+        db,
+        &contracts.iter().collect::<Vec<_>>(),
+        CompilerConfig {
+            diagnostics_reporter: DiagnosticsReporter::ignoring().allow_warnings(),
+            ..Default::default()
+        },
+    )
+    .context("Failed to compile the contracts")?;
+
+    contracts
+        .iter()
+        .zip(contract_classes)
+        .map(|(contract, contract_class)| {
+            let name = contract.submodule_id.full_path(db);
+            let compiled_class =
+                CasmContractClass::from_contract_class(contract_class.clone(), false, usize::MAX)
+                    .with_context(|| format!("Failed to compile `{name}` to CASM"))?;
+            Ok(ContractArtifacts {
+                compiled_class_hash: format!("{:#x}", compiled_class.compiled_class_hash()),
+                contract_class: serde_json::to_value(&contract_class)?,
+                compiled_class: serde_json::to_value(&compiled_class)?,
+                name,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod compiler_tests {
+    use super::*;
+
+    #[test]
+    fn test_compile_program_artifacts() {
+        let code = "fn main() -> felt252 { 1 + 2 }".to_string();
+        let output = compile(
+            code,
+            &[
+                ArtifactKind::Sierra,
+                ArtifactKind::SierraJson,
+                ArtifactKind::Casm,
+            ],
+        )
+        .unwrap();
+        assert!(output.sierra.unwrap().contains("lib::main"));
+        assert_eq!(output.sierra_json.unwrap()["version"], 1);
+        assert!(output.casm.unwrap().contains("ret;"));
+        assert_eq!(output.contracts, None);
+    }
+
+    #[test]
+    fn test_compile_only_requested() {
+        let output = compile("fn main() {}".to_string(), &[]).unwrap();
+        assert_eq!(output, CompiledArtifacts::default());
+    }
+
+    #[test]
+    fn test_compile_error() {
+        let error = compile("fn main() -> felt252 {}".to_string(), &[]).unwrap_err();
+        assert!(matches!(error, RunnerError::Compilation(_)));
+    }
+
+    #[test]
+    fn test_compile_contracts() {
+        let code = r#"
+            #[starknet::interface]
+            trait ICounter<T> {
+                fn get(self: @T) -> u32;
+            }
+
+            #[starknet::contract]
+            mod counter {
+                use starknet::storage::StoragePointerReadAccess;
+
+                #[storage]
+                struct Storage {
+                    value: u32,
+                }
+
+                #[abi(embed_v0)]
+                impl CounterImpl of super::ICounter<ContractState> {
+                    fn get(self: @ContractState) -> u32 {
+                        self.value.read()
+                    }
+                }
+            }
+        "#;
+        let output = compile(code.to_string(), &[ArtifactKind::Contracts]).unwrap();
+        let contracts = output.contracts.unwrap();
+        assert_eq!(contracts.len(), 1);
+        assert_eq!(contracts[0].name, "lib::counter");
+        assert!(contracts[0].contract_class["sierra_program"].is_array());
+        assert!(contracts[0].compiled_class["bytecode"].is_array());
+        assert!(contracts[0].compiled_class_hash.starts_with("0x"));
+    }
+}
@@ -0,0 +1,71 @@
+use std::path::PathBuf;
+
+use cairo_lang_compiler::db::{RootDatabase, validate_corelib};
+use cairo_lang_filesystem::db::{
+    CrateConfiguration, CrateSettings, Edition, ExperimentalFeaturesConfig, FilesGroupEx,
+    init_dev_corelib,
+};
+use cairo_lang_filesystem::ids::CrateId;
+use semver::Version;
+
+use crate::error::RunnerError;
+use crate::main_runner::virtual_directory;
+
+include!(concat!(env!("OUT_DIR"), "/corelib.rs"));
+
+/// A corelib directory to use instead of the embedded one, from the `CORELIB_PATH` environment
+/// variable.
+pub fn corelib_path() -> Option<PathBuf> {
+    std::env::var_os("CORELIB_PATH").map(PathBuf::from)
+}
+
+/// Sets up the `core` crate of `db`, from [`corelib_path`] or from the corelib embedded in the
+/// runners, whose version matches the compiler's.
+pub fn setup_corelib(db: &mut RootDatabase) -> Result<(), RunnerError> {
+    match corelib_path() {
+        Some(path) => init_dev_corelib(db, path),
+        None => {
+            let root = virtual_directory(db, "core/", EMBEDDED_CORELIB_FILES.iter().copied());
+            // The settings of `init_dev_corelib`.
+            let settings = CrateSettings {
+                name: None,
+                edition: Edition::V2024_07,
+                version: Version::parse(EMBEDDED_CORELIB_VERSION).ok(),
+                cfg_set: Default::default(),
+                dependencies: Default::default(),
+                experimental_features: ExperimentalFeaturesConfig {
+                    negative_impls: true,
+                    associated_item_constraints: true,
+                    coupons: true,
+                },
+            };
+            let core = CrateId::core(db);
+            db.set_crate_config(
+                core,
+                Some(CrateConfiguration {
+                    root,
+                    settings,
+                    cache_file: None,
+                }),
+            );
+        }
+    }
+    validate_corelib(db).map_err(|e| RunnerError::Internal(e.to_string()))
+}
+
+#[cfg(test)]
+mod corelib_tests {
+    use super::*;
+    use cairo_lang_filesystem::db::CORELIB_VERSION;
+
+    #[test]
+    fn test_embedded_corelib() {
+        assert_eq!(EMBEDDED_CORELIB_VERSION, CORELIB_VERSION);
+        let paths: Vec<&str> = EMBEDDED_CORELIB_FILES
+            .iter()
+            .map(|(path, _)| *path)
+            .collect();
+        assert!(paths.contains(&"lib.cairo"));
+        assert!(paths.contains(&"starknet/syscalls.cairo"));
+    }
+}
@@ -0,0 +1,149 @@
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+
+use cairo_lang_compiler::db::RootDatabase;
+use cairo_lang_defs::db::DefsGroup;
+use cairo_lang_filesystem::cfg::{Cfg, CfgSet};
+use cairo_lang_filesystem::ids::CrateId;
+use cairo_lang_semantic::db::SemanticGroup;
+use cairo_lang_starknet::starknet_plugin_suite;
+use cairo_lang_test_plugin::test_plugin_suite;
+
+use crate::corelib::setup_corelib;
+use crate::error::RunnerError;
+
+/// What a database compiles, which decides its plugins and cfg.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DatabaseKind {
+    /// Programs to run, with the Starknet plugins and the `test` target cfg.
+    Program,
+    /// Tests, with the test and Starknet plugins and the `test` cfg.
+    Tests,
+}
+
+/// The default number of idle databases kept for each kind, see [`database_pool_size`].
+pub const DEFAULT_DATABASE_POOL_SIZE: usize = 4;
+
+/// The number of idle databases kept for each kind, from the `DATABASE_POOL_SIZE` environment
+/// variable or [`DEFAULT_DATABASE_POOL_SIZE`].
+pub fn database_pool_size() -> usize {
+    std::env::var("DATABASE_POOL_SIZE")
+        .ok()
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(DEFAULT_DATABASE_POOL_SIZE)
+}
+
+/// The number of compilations after which a database is dropped instead of returning to its
+/// pool, bounding the memory taken by the sources it interned.
+const MAX_DATABASE_USES: usize = 256;
+
+struct IdleDatabase {
+    db: RootDatabase,
+    uses: usize,
+}
+
+static PROGRAM_DATABASES: Mutex<Vec<IdleDatabase>> = Mutex::new(Vec::new());
+static TESTS_DATABASES: Mutex<Vec<IdleDatabase>> = Mutex::new(Vec::new());
+
+impl DatabaseKind {
+    fn pool(self) -> &'static Mutex<Vec<IdleDatabase>> {
+        match self {
+            DatabaseKind::Program => &PROGRAM_DATABASES,
+            DatabaseKind::Tests => &TESTS_DATABASES,
+        }
+    }
+
+    /// Builds a database and analyzes its corelib, which would otherwise dominate the first
+    /// compilation in it.
+    fn build(self) -> Result<RootDatabase, RunnerError> {
+        let mut db_builder = RootDatabase::builder();
+        // The `TEST_CLASS_HASH` of contracts, which identifies them to the deploy syscall, only
+        // exists for the `test` target.
+        let mut cfg_set = CfgSet::from_iter([Cfg::kv("target", "test")]);
+        if self == DatabaseKind::Tests {
+            cfg_set.insert(Cfg::name("test"));
+            db_builder.with_default_plugin_suite(test_plugin_suite());
+        }
+        db_builder.with_cfg(cfg_set);
+        db_builder.with_default_plugin_suite(starknet_plugin_suite());
+        let mut db = db_builder
+            .build()
+            .map_err(|e| RunnerError::Internal(format!("Failed to build the database: {e}")))?;
+        setup_corelib(&mut db)?;
+
+        for module_id in db.crate_modules(CrateId::core(&db)).iter() {
+            // Only memoizes the analysis, the corelib has no errors to report.
+            let _ = db.module_semantic_diagnostics(*module_id);
+        }
+        Ok(db)
+    }
+}
+
+/// A database with an analyzed corelib, taken from the pool of its kind and returned to it when
+/// dropped. The next user of the database replaces the `lib` crate, and the corelib queries stay
+/// memoized in between.
+pub struct PooledDatabase {
+    kind: DatabaseKind,
+    db: Option<RootDatabase>,
+    uses: usize,
+}
+
+impl PooledDatabase {
+    /// Takes an idle database of `kind`, or builds one if there is none.
+    pub fn checkout(kind: DatabaseKind) -> Result<Self, RunnerError> {
+        let idle = kind.pool().lock().unwrap().pop();
+        let IdleDatabase { db, uses } = match idle {
+            Some(idle) => idle,
+            None => IdleDatabase {
+                db: kind.build()?,
+                uses: 0,
+            },
+        };
+        Ok(Self {
+            kind,
+            db: Some(db),
+            uses: uses + 1,
+        })
+    }
+}
+
+impl Deref for PooledDatabase {
+    type Target = RootDatabase;
+
+    fn deref(&self) -> &RootDatabase {
+        self.db.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledDatabase {
+    fn deref_mut(&mut self) -> &mut RootDatabase {
+        self.db.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledDatabase {
+    fn drop(&mut self) {
+        // A panic may have interrupted the database in the middle of a query.
+        if std::thread::panicking() || self.uses >= MAX_DATABASE_USES {
+            return;
+        }
+        let Ok(mut pool) = self.kind.pool().lock() else {
+            return;
+        };
+        if pool.len() < database_pool_size() {
+            pool.push(IdleDatabase {
+                db: self.db.take().unwrap(),
+                uses: self.uses,
+            });
+        }
+    }
+}
+
+/// Puts a database of each kind in the pools, so that the first requests of a server don't wait
+/// for the corelib analysis.
+pub fn warm_up_databases() -> Result<(), RunnerError> {
+    for kind in [DatabaseKind::Program, DatabaseKind::Tests] {
+        drop(PooledDatabase::checkout(kind)?);
+    }
+    Ok(())
+}
@@ -0,0 +1,361 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{Context, anyhow, bail, ensure};
+use cairo_lang_compiler::db::RootDatabase;
+use cairo_lang_defs::db::DefsGroup;
+use cairo_lang_defs::ids::TopLevelLanguageElementId;
+use cairo_lang_filesystem::ids::CrateId;
+use cairo_lang_runner::short_string::{as_cairo_short_string, as_cairo_short_string_ex};
+use cairo_lang_semantic::db::SemanticGroup;
+use cairo_lang_sierra::extensions::ConcreteType;
+use cairo_lang_sierra::extensions::core::{CoreLibfunc, CoreType, CoreTypeConcrete};
+use cairo_lang_sierra::extensions::starknet::StarknetTypeConcrete;
+use cairo_lang_sierra::ids::ConcreteTypeId;
+use cairo_lang_sierra::program::{Function, GenericArg, Program};
+use cairo_lang_sierra::program_registry::ProgramRegistry;
+use cairo_lang_sierra_to_casm::invocations::enm::get_variant_selector;
+use cairo_lang_sierra_type_size::{TypeSizeMap, get_type_size_map};
+use cairo_lang_utils::byte_array::{BYTE_ARRAY_MAGIC, BYTES_IN_WORD};
+use cairo_runner_types::PanicItem;
+use num_bigint::BigInt;
+use serde_json::{Map, Value};
+use starknet_types_core::felt::Felt as Felt252;
+
+use crate::args::{user_return_types, user_type_name};
+
+/// Formats a felt as a decimal string.
+pub fn felt_to_string(value: &Felt252) -> String {
+    value.to_string()
+}
+
+/// Decodes panic data into its `ByteArray` strings and plain felts.
+pub fn decode_panic_data(values: &[Felt252]) -> Vec<PanicItem> {
+    let magic = Felt252::from_hex(BYTE_ARRAY_MAGIC).unwrap();
+    let mut items = vec![];
+    let mut rest = values;
+    while let Some((first, tail)) = rest.split_first() {
+        rest = tail;
+        if *first == magic
+            && let Some((value, consumed)) = try_decode_byte_array(rest)
+        {
+            rest = &rest[consumed..];
+            items.push(PanicItem::String { value });
+            continue;
+        }
+        items.push(PanicItem::Felt {
+            value: format!("{:#x}", first.to_biguint()),
+            short_string: as_cairo_short_string(first),
+        });
+    }
+    items
+}
+
+/// Formats the felts of a `print` the way the runner writes them to stdout: a lone string as is,
+/// otherwise one line per item.
+pub fn format_for_debug(values: &[Felt252]) -> String {
+    let items = decode_panic_data(values);
+    if let [PanicItem::String { value }] = &items[..] {
+        return value.clone();
+    }
+    items
+        .iter()
+        .map(|item| match item {
+            PanicItem::String { value } => format!("{value}\n"),
+            item => format!("[DEBUG]\t{item}\n"),
+        })
+        .collect()
+}
+
+/// Tries to decode a serialized `ByteArray` from the start of `values`.
+/// Returns the string and the number of felts it spans.
+fn try_decode_byte_array(values: &[Felt252]) -> Option<(String, usize)> {
+    let num_full_words = usize::try_from(values.first()?.to_biguint()).ok()?;
+    let full_words = values.get(1..1 + num_full_words)?;
+    let pending_word = values.get(1 + num_full_words)?;
+    let pending_word_len = usize::try_from(values.get(2 + num_full_words)?.to_biguint()).ok()?;
+
+    let value = byte_array_to_string(full_words, pending_word, pending_word_len)?;
+    Some((value, num_full_words + 3))
+}
+
+/// The string of a `ByteArray`'s full words, pending word and pending word length, or `None` if
+/// the words are not valid.
+fn byte_array_to_string(
+    full_words: &[Felt252],
+    pending_word: &Felt252,
+    pending_word_len: usize,
+) -> Option<String> {
+    let mut value = full_words
+        .iter()
+        .map(|word| as_cairo_short_string_ex(word, BYTES_IN_WORD))
+        .collect::<Option<Vec<String>>>()?
+        .join("");
+    value += &as_cairo_short_string_ex(pending_word, pending_word_len)?;
+    Some(value)
+}
+
+/// The member names of the structs and the variant names of the enums of a program, in
+/// declaration order, keyed by the path of their type, e.g. `lib::Point`.
+pub type MemberNames = HashMap<String, Vec<String>>;
+
+/// The path of a user type's debug name, without its generic arguments.
+fn type_path(name: &str) -> &str {
+    name.split_once("::<").map_or(name, |(path, _)| path)
+}
+
+/// Collects the [`MemberNames`] of the structs and enums of `program` that are declared in
+/// `crate_ids`, which the Sierra program itself doesn't name.
+pub fn collect_member_names(
+    db: &RootDatabase,
+    crate_ids: &[CrateId],
+    program: &Program,
+) -> MemberNames {
+    let paths: HashSet<&str> = program
+        .type_declarations
+        .iter()
+        .filter_map(
+            |declaration| match declaration.long_id.generic_args.first() {
+                Some(GenericArg::UserType(id)) => id.debug_name.as_deref(),
+                _ => None,
+            },
+        )
+        .map(type_path)
+        .collect();
+    let mut names = MemberNames::new();
+    for crate_id in crate_ids {
+        for module_id in db.crate_modules(*crate_id).iter() {
+            for struct_id in db.module_structs_ids(*module_id).unwrap_or_default().iter() {
+                let path = struct_id.full_path(db);
+                if !paths.contains(path.as_str()) {
+                    continue;
+                }
+                if let Ok(members) = db.struct_members(*struct_id) {
+                    names.insert(path, members.keys().map(|name| name.to_string()).collect());
+                }
+            }
+            for enum_id in db.module_enums_ids(*module_id).unwrap_or_default().iter() {
+                let path = enum_id.full_path(db);
+                if !paths.contains(path.as_str()) {
+                    continue;
+                }
+                if let Ok(variants) = db.enum_variants(*enum_id) {
+                    names.insert(path, variants.keys().map(|name| name.to_string()).collect());
+                }
+            }
+        }
+    }
+    names
+}
+
+/// Decodes the cells returned by `func` into JSON by their Cairo type:
+/// - integers up to 64 bits as numbers, and wider integers and `felt252` as decimal strings,
+/// - `bool` as a boolean and `ByteArray` as a string,
+/// - arrays, spans and tuples as arrays, and structs as objects keyed by their member names,
+/// - enums as their variant name, or `{"Variant": payload}` when the variant holds a value.
+///
+/// Returns `None` when the function returns nothing, and fails on types that are not decoded,
+/// such as dictionaries.
+pub fn decode_return_value(
+    program: &Program,
+    func: &Function,
+    values: &[Felt252],
+    memory: &[Option<Felt252>],
+    member_names: &MemberNames,
+) -> anyhow::Result<Option<Value>> {
+    let registry = ProgramRegistry::<CoreType, CoreLibfunc>::new(program)?;
+    let type_sizes =
+        get_type_size_map(program, &registry).context("Failed to compute the type sizes.")?;
+    let decoder = ValueDecoder {
+        registry: &registry,
+        type_sizes: &type_sizes,
+        memory,
+        member_names,
+    };
+    let (returns, _) = user_return_types(&registry, func)?;
+    let mut cells = values;
+    let mut decoded = vec![];
+    for ty in &returns {
+        decoded.push(decoder.decode(ty, &mut cells)?);
+    }
+    ensure!(
+        cells.is_empty(),
+        "Unexpected return values left after decoding."
+    );
+    Ok(match decoded.len() {
+        0 => None,
+        1 => decoded.pop(),
+        _ => Some(Value::Array(decoded)),
+    })
+}
+
+struct ValueDecoder<'a> {
+    registry: &'a ProgramRegistry<CoreType, CoreLibfunc>,
+    type_sizes: &'a TypeSizeMap,
+    memory: &'a [Option<Felt252>],
+    member_names: &'a MemberNames,
+}
+
+impl ValueDecoder<'_> {
+    /// Decodes a value of type `ty` from the start of `cells`, and advances `cells` past it.
+    fn decode(&self, ty: &ConcreteTypeId, cells: &mut &[Felt252]) -> anyhow::Result<Value> {
+        let size = self.size(ty)?;
+        ensure!(cells.len() >= size, "Missing cells for a value of `{ty}`.");
+        let (value_cells, rest) = cells.split_at(size);
+        *cells = rest;
+
+        Ok(match self.registry.get_type(ty)? {
+            CoreTypeConcrete::Uint8(_)
+            | CoreTypeConcrete::Uint16(_)
+            | CoreTypeConcrete::Uint32(_)
+            | CoreTypeConcrete::Uint64(_) => {
+                Value::from(u64::try_from(value_cells[0].to_biguint())?)
+            }
+            CoreTypeConcrete::Sint8(_)
+            | CoreTypeConcrete::Sint16(_)
+            | CoreTypeConcrete::Sint32(_)
+            | CoreTypeConcrete::Sint64(_) => {
+                Value::from(i64::try_from(felt_to_signed(&value_cells[0]))?)
+            }
+            CoreTypeConcrete::Sint128(_) | CoreTypeConcrete::BoundedInt(_) => {
+                Value::String(felt_to_signed(&value_cells[0]).to_string())
+            }
+            CoreTypeConcrete::Felt252(_)
+            | CoreTypeConcrete::Bytes31(_)
+            | CoreTypeConcrete::Uint128(_)
+            | CoreTypeConcrete::Starknet(
+                StarknetTypeConcrete::ContractAddress(_)
+                | StarknetTypeConcrete::ClassHash(_)
+                | StarknetTypeConcrete::StorageAddress(_)
+                | StarknetTypeConcrete::StorageBaseAddress(_),
+            ) => Value::String(felt_to_string(&value_cells[0])),
+            CoreTypeConcrete::Snapshot(inner) | CoreTypeConcrete::NonZero(inner) => {
+                self.decode(&inner.ty, &mut &value_cells[..])?
+            }
+            CoreTypeConcrete::Box(inner) => {
+                let address = self.address(&value_cells[0])?;
+                let boxed = self.read(address, address + self.size(&inner.ty)?)?;
+                self.decode(&inner.ty, &mut &boxed[..])?
+            }
+            CoreTypeConcrete::Array(array) => {
+                Value::Array(self.decode_array(&array.ty, value_cells)?)
+            }
+            CoreTypeConcrete::Enum(enm) => {
+                let name = user_type_name(&enm.info).unwrap_or_default();
+                let selector = usize::try_from(value_cells[0].to_biguint())?;
+                let index = (0..enm.variants.len())
+                    .find(|index| {
+                        get_variant_selector(enm.variants.len(), *index).ok() == Some(selector)
+                    })
+                    .ok_or_else(|| anyhow!("Invalid selector of `{ty}`."))?;
+                if name == "core::bool" {
+                    return Ok(Value::Bool(index == 1));
+                }
+                // The payload is aligned to the end of the enum, after the padding of smaller
+                // variants.
+                let variant = &enm.variants[index];
+                let payload = &value_cells[size - self.size(variant)?..];
+                let variant_name = self
+                    .member_names
+                    .get(type_path(name))
+                    .and_then(|names| names.get(index).cloned())
+                    .unwrap_or_else(|| index.to_string());
+                match self.registry.get_type(variant)? {
+                    CoreTypeConcrete::Struct(unit) if unit.members.is_empty() => {
+                        Value::String(variant_name)
+                    }
+                    _ => Value::Object(Map::from_iter([(
+                        variant_name,
+                        self.decode(variant, &mut &payload[..])?,
+                    )])),
+                }
+            }
+            CoreTypeConcrete::Struct(strct) => {
+                let name = user_type_name(&strct.info).unwrap_or_default();
+                let mut members = value_cells;
+                match name {
+                    "core::integer::u256" => {
+                        let [low, high] = value_cells else {
+                            bail!("Unexpected layout of `{ty}`.");
+                        };
+                        let value = (high.to_biguint() << 128u32) + low.to_biguint();
+                        Value::String(value.to_string())
+                    }
+                    "core::byte_array::ByteArray" => {
+                        let [start, end, pending_word, pending_word_len] = value_cells else {
+                            bail!("Unexpected layout of `{ty}`.");
+                        };
+                        let words = self.read(self.address(start)?, self.address(end)?)?;
+                        let pending_word_len = usize::try_from(pending_word_len.to_biguint())?;
+                        let value = byte_array_to_string(&words, pending_word, pending_word_len)
+                            .ok_or_else(|| anyhow!("Invalid `ByteArray` words."))?;
+                        Value::String(value)
+                    }
+                    _ if type_path(name) == "core::array::Span" => {
+                        self.decode(&strct.members[0], &mut members)?
+                    }
+                    _ => {
+                        let mut values = vec![];
+                        for member in &strct.members {
+                            values.push(self.decode(member, &mut members)?);
+                        }
+                        match self.member_names.get(type_path(name)) {
+                            Some(names) if names.len() == values.len() => {
+                                Value::Object(names.iter().cloned().zip(values).collect())
+                            }
+                            // Tuples, and structs whose names were not collected.
+                            _ => Value::Array(values),
+                        }
+                    }
+                }
+            }
+            other => bail!(
+                "Decoding `{}` values is not supported.",
+                other.info().long_id.generic_id
+            ),
+        })
+    }
+
+    /// Decodes the elements of an array of `ty` from its start and end addresses.
+    fn decode_array(&self, ty: &ConcreteTypeId, cells: &[Felt252]) -> anyhow::Result<Vec<Value>> {
+        let [start, end] = cells else {
+            bail!("Unexpected layout of an array.");
+        };
+        let elements = self.read(self.address(start)?, self.address(end)?)?;
+        let mut elements = &elements[..];
+        let mut values = vec![];
+        while !elements.is_empty() {
+            values.push(self.decode(ty, &mut elements)?);
+        }
+        Ok(values)
+    }
+
+    fn size(&self, ty: &ConcreteTypeId) -> anyhow::Result<usize> {
+        let size = self
+            .type_sizes
+            .get(ty)
+            .ok_or_else(|| anyhow!("Unknown size of `{ty}`."))?;
+        Ok(usize::try_from(*size)?)
+    }
+
+    fn address(&self, cell: &Felt252) -> anyhow::Result<usize> {
+        Ok(usize::try_from(cell.to_biguint())?)
+    }
+
+    /// Reads the memory cells in `start..end`, which must all be initialized.
+    fn read(&self, start: usize, end: usize) -> anyhow::Result<Vec<Felt252>> {
+        self.memory
+            .get(start..end)
+            .and_then(|cells| cells.iter().copied().collect())
+            .ok_or_else(|| anyhow!("Invalid memory range {start}..{end}."))
+    }
+}
+
+/// The value of a felt as a signed integer, negative above `P / 2`.
+fn felt_to_signed(value: &Felt252) -> BigInt {
+    let negated = -*value;
+    if negated.to_biguint() < value.to_biguint() {
+        -BigInt::from(negated.to_biguint())
+    } else {
+        BigInt::from(value.to_biguint())
+    }
+}
@@ -0,0 +1,105 @@
+use std::fmt;
+
+use cairo_lang_defs::ids::ModuleId;
+use cairo_lang_diagnostics::{DiagnosticEntry, Diagnostics, Severity};
+use cairo_lang_filesystem::{db::FilesGroup, ids::CrateId};
+use cairo_lang_lowering::db::LoweringGroup;
+use cairo_lang_parser::ParserDiagnostic;
+use cairo_lang_utils::{Upcast, unordered_hash_set::UnorderedHashSet};
+use cairo_runner_types::{Diagnostic, DiagnosticSeverity};
+
+/// Compilation failed, with the diagnostics that caused it.
+#[derive(Debug)]
+pub struct CompilationError {
+    pub message: String,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl fmt::Display for CompilationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for CompilationError {}
+
+/// Returns whether any of the diagnostics is an error.
+pub fn has_errors(diagnostics: &[Diagnostic]) -> bool {
+    diagnostics
+        .iter()
+        .any(|d| d.severity == DiagnosticSeverity::Error)
+}
+
+/// Collects the parser, semantic and lowering diagnostics of the given crates, mapped back to
+/// the user's source files.
+pub fn collect_diagnostics(db: &dyn LoweringGroup, crate_ids: &[CrateId]) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    for crate_id in crate_ids {
+        let mut processed_file_ids = UnorderedHashSet::<_>::default();
+        for module_id in db.crate_modules(*crate_id).iter() {
+            if let Ok(module_files) = db.module_files(*module_id) {
+                for file_id in module_files.iter().copied() {
+                    if processed_file_ids.insert(file_id) {
+                        push_group(
+                            &mut diagnostics,
+                            db.upcast(),
+                            db.file_syntax_diagnostics(file_id),
+                        );
+                    }
+                }
+            }
+            if let Ok(group) = db.module_semantic_diagnostics(*module_id) {
+                push_group(&mut diagnostics, db.upcast(), group);
+            }
+            if let Ok(group) = db.module_lowering_diagnostics(*module_id) {
+                push_group(&mut diagnostics, db.upcast(), group);
+            }
+        }
+        // Make sure the crate root is reachable at all.
+        if db.module_main_file(ModuleId::CrateRoot(*crate_id)).is_err() {
+            diagnostics.push(Diagnostic {
+                severity: DiagnosticSeverity::Error,
+                message: "Failed to get main module file".into(),
+                file: "lib.cairo".into(),
+                start: (0, 0),
+                end: (0, 0),
+                code: None,
+            });
+        }
+    }
+    diagnostics
+}
+
+/// Converts the diagnostics of a file parsed on its own, outside of a crate.
+pub fn parser_diagnostics(
+    db: &(dyn FilesGroup + 'static),
+    group: Diagnostics<ParserDiagnostic>,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    push_group(&mut diagnostics, db, group);
+    diagnostics
+}
+
+/// Converts the entries of a diagnostics group and appends them to `diagnostics`.
+fn push_group<TEntry: DiagnosticEntry>(
+    diagnostics: &mut Vec<Diagnostic>,
+    db: &TEntry::DbType,
+    group: Diagnostics<TEntry>,
+) {
+    let files_db = db.upcast();
+    for entry in group.get_diagnostics_without_duplicates(db) {
+        let location = entry.location(db).user_location(files_db);
+        let span = location.span.position_in_file(files_db, location.file_id);
+        diagnostics.push(Diagnostic {
+            severity: match entry.severity() {
+                Severity::Error => DiagnosticSeverity::Error,
+                Severity::Warning => DiagnosticSeverity::Warning,
+            },
+            message: entry.format(db),
+            file: location.file_id.file_name(files_db),
+            start: span.map_or((0, 0), |s| (s.start.line, s.start.col)),
+            end: span.map_or((0, 0), |s| (s.end.line, s.end.col)),
+            code: entry.error_code().map(|code| code.to_string()),
+        });
+    }
+}
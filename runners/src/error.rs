@@ -0,0 +1,65 @@
+use std::fmt;
+
+use cairo_lang_compiler::project::ProjectError;
+
+use crate::diagnostics::CompilationError;
+
+/// Why the runners failed to produce a result.
+#[derive(Debug)]
+pub enum RunnerError {
+    /// The project failed to compile.
+    Compilation(CompilationError),
+    /// The runner could not be prepared, e.g. the arguments do not match the entrypoint.
+    RunnerSetup(String),
+    /// No function matches the requested entrypoint.
+    EntrypointNotFound(String),
+    /// No live session has the requested id, e.g. it expired.
+    SessionNotFound(String),
+    /// The VM failed while running the program.
+    Execution(String),
+    /// A failure of the runners themselves, such as a panic.
+    Internal(String),
+}
+
+impl RunnerError {
+    /// The HTTP status code the servers answer with.
+    pub fn http_status(&self) -> u16 {
+        match self {
+            RunnerError::Compilation(_) | RunnerError::Execution(_) => 422,
+            RunnerError::RunnerSetup(_) => 400,
+            RunnerError::EntrypointNotFound(_) | RunnerError::SessionNotFound(_) => 404,
+            RunnerError::Internal(_) => 500,
+        }
+    }
+}
+
+impl fmt::Display for RunnerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunnerError::Compilation(error) => write!(f, "{error}"),
+            RunnerError::RunnerSetup(message)
+            | RunnerError::EntrypointNotFound(message)
+            | RunnerError::SessionNotFound(message)
+            | RunnerError::Execution(message)
+            | RunnerError::Internal(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for RunnerError {}
+
+impl From<CompilationError> for RunnerError {
+    fn from(error: CompilationError) -> Self {
+        RunnerError::Compilation(error)
+    }
+}
+
+/// The project's files can't form a crate, e.g. `lib.cairo` is missing.
+impl From<ProjectError> for RunnerError {
+    fn from(error: ProjectError) -> Self {
+        RunnerError::Compilation(CompilationError {
+            message: error.to_string(),
+            diagnostics: vec![],
+        })
+    }
+}
@@ -0,0 +1,308 @@
+use std::{any::Any, collections::HashMap};
+
+use cairo_lang_casm::hints::{CoreHint, CoreHintBase, Hint};
+use cairo_lang_runnable_utils::builder::{EntryCodeConfig, RunnableBuilder};
+use cairo_lang_runner::{
+    Arg, CairoHintProcessor, ProfilingInfoCollectionConfig, RunResultStarknet, RunResultValue,
+    SierraCasmRunner, StarknetState, build_hints_dict,
+    casm_run::{self, extract_relocatable, vm_get_range},
+    initialize_vm,
+};
+use cairo_lang_sierra::{
+    extensions::{NamedType, enm::EnumType, gas::GasBuiltinType},
+    program::{Function, GenericArg, StatementIdx},
+};
+use cairo_vm::{
+    hint_processor::hint_processor_definition::{HintProcessorLogic, HintReference},
+    serde::deserialize_program::ApTracking,
+    types::{exec_scope::ExecutionScopes, relocatable::MaybeRelocatable},
+    vm::{
+        errors::{hint_errors::HintError, vm_errors::VirtualMachineError},
+        runners::cairo_runner::{ResourceTracker, RunResources},
+        trace::trace_entry::RelocatedTraceEntry,
+        vm_core::VirtualMachine,
+    },
+};
+use starknet_types_core::felt::Felt as Felt252;
+
+use crate::backtrace::{gas_withdrawal_failed, panic_statements};
+use crate::decode::format_for_debug;
+use crate::error::RunnerError;
+use crate::profiling::collect_profiling_info;
+
+/// The result of [`run_function`].
+pub struct FunctionRun {
+    /// The result of the run, `None` if it hit the step limit.
+    pub result: Option<RunResultStarknet>,
+    /// What the program printed, in the order it was printed.
+    pub stdout: String,
+    /// Where the run panicked, see [`panic_statements`]. Empty unless it panicked.
+    pub panic_statements: Vec<StatementIdx>,
+    /// Whether the run panicked as it ran out of gas, see [`gas_withdrawal_failed`].
+    pub out_of_gas: bool,
+    /// The trace of the run, if it was traced.
+    pub trace: Option<RunTrace>,
+}
+
+/// The trace of a run, with relocated addresses.
+pub struct RunTrace {
+    /// The registers before each step.
+    pub entries: Vec<RelocatedTraceEntry>,
+    /// The address of each memory segment, followed by the end of the last one.
+    pub segments: Vec<usize>,
+}
+
+/// The options of [`run_function`].
+#[derive(Clone, Default)]
+pub struct RunOptions {
+    /// The gas available to the run, if the program is metered.
+    pub available_gas: Option<usize>,
+    /// The number of CASM steps after which the VM is stopped. Unlimited when `None`.
+    pub max_steps: Option<usize>,
+    /// Trace the run, see [`FunctionRun::trace`].
+    pub trace: bool,
+    /// Collect the profiling info of the run into its result.
+    pub profiling: Option<ProfilingInfoCollectionConfig>,
+    /// The Starknet state the run starts from.
+    pub starknet_state: StarknetState,
+}
+
+/// Runs `func` like [`SierraCasmRunner::run_function_with_starknet_context`], with `args` grouped
+/// per parameter. The VM is stopped after `options.max_steps` steps, and the program's prints are
+/// captured instead of going to the server's stdout. The function is executed once, and its trace
+/// is kept if `options.trace` is set.
+///
+/// `builder` must be built from the runner's program and metadata config.
+pub fn run_function(
+    runner: &SierraCasmRunner,
+    builder: &RunnableBuilder,
+    func: &Function,
+    mut args: Vec<Vec<Arg>>,
+    options: RunOptions,
+) -> Result<FunctionRun, RunnerError> {
+    let RunOptions {
+        available_gas,
+        max_steps,
+        trace,
+        profiling,
+        starknet_state,
+    } = options;
+    if func
+        .signature
+        .param_types
+        .iter()
+        .any(|ty| builder.type_long_id(ty).generic_id == GasBuiltinType::ID)
+    {
+        let gas = runner
+            .get_initial_available_gas(func, available_gas)
+            .map_err(|e| RunnerError::RunnerSetup(e.to_string()))?;
+        args.insert(0, vec![Arg::Value(Felt252::from(gas))]);
+    }
+
+    let (assembled_program, builtins) = builder
+        .assemble_function_program(func, EntryCodeConfig::testing())
+        .map_err(|e| RunnerError::RunnerSetup(e.to_string()))?;
+    let (hints_dict, string_to_hint) = build_hints_dict(&assembled_program.hints);
+    let mut hint_processor =
+        CapturingHintProcessor::new(runner, args, starknet_state, string_to_hint, max_steps);
+    let data_len = assembled_program.bytecode.len();
+    let data = assembled_program
+        .bytecode
+        .iter()
+        .map(|value| MaybeRelocatable::from(Felt252::from(value)))
+        .collect();
+    // The VM runner is built here rather than by `SierraCasmRunner::run_function`, which drops
+    // the trace and the segments of the run.
+    let run = casm_run::build_cairo_runner(data, builtins, hints_dict).and_then(|mut vm_runner| {
+        casm_run::run_function_with_runner(
+            |vm| initialize_vm(vm, data_len),
+            &mut hint_processor,
+            &mut vm_runner,
+        )?;
+        Ok(vm_runner)
+    });
+    let mut vm_runner = match run {
+        Ok(vm_runner) => vm_runner,
+        Err(_) if hint_processor.consumed() => {
+            return Ok(FunctionRun {
+                result: None,
+                stdout: hint_processor.stdout,
+                panic_statements: vec![],
+                out_of_gas: false,
+                trace: None,
+            });
+        }
+        Err(error) => {
+            return Err(RunnerError::Execution(format!(
+                "Failed to run the function: {error}"
+            )));
+        }
+    };
+    let entries = vm_runner.relocated_trace.take().unwrap_or_default();
+    let memory = std::mem::take(&mut vm_runner.relocated_memory);
+    let mut basic_resources = vm_runner
+        .get_execution_resources()
+        .map_err(|e| RunnerError::Execution(format!("Failed to run the function: {e}")))?;
+    // The header ends with the `ret` of the run, so the steps before the program starts and after
+    // it returns are the ones at or before that `ret`.
+    let Some(&RelocatedTraceEntry {
+        pc: header_end, ap, ..
+    }) = entries.last()
+    else {
+        return Err(RunnerError::Execution("The run left no trace.".to_string()));
+    };
+    let in_program = |entry: &RelocatedTraceEntry| entry.pc > header_end;
+    basic_resources.n_steps -= entries.iter().position(in_program).unwrap_or(1) - 1;
+    basic_resources.n_steps -= entries.iter().rev().position(in_program).unwrap_or(1) - 1;
+
+    let (results_data, gas_counter) = runner.get_results_data(
+        &builder.generic_id_and_size_from_concrete(&func.signature.ret_types),
+        &memory,
+        ap,
+    );
+    let value = match results_data.into_iter().next() {
+        Some((_, values)) => SierraCasmRunner::handle_main_return_value(
+            panic_wrapped_size(builder, func),
+            values,
+            &memory,
+        ),
+        // No result type, so no panic.
+        None => RunResultValue::Success(vec![]),
+    };
+    let (panic_statements, out_of_gas) = match value {
+        RunResultValue::Panic(_) => (
+            panic_statements(builder, &entries, &memory),
+            gas_withdrawal_failed(builder, &entries),
+        ),
+        RunResultValue::Success(_) => (vec![], false),
+    };
+    let profiling_info = profiling
+        .as_ref()
+        .map(|config| collect_profiling_info(builder, &entries, config));
+
+    let CapturingHintProcessor { inner, stdout } = hint_processor;
+    let mut used_resources = inner.syscalls_used_resources;
+    used_resources.basic_resources += &basic_resources;
+    Ok(FunctionRun {
+        result: Some(RunResultStarknet {
+            gas_counter,
+            memory,
+            value,
+            starknet_state: inner.starknet_state,
+            used_resources,
+            profiling_info,
+        }),
+        stdout,
+        panic_statements,
+        out_of_gas,
+        trace: trace.then(|| RunTrace {
+            segments: vm_runner
+                .vm
+                .segments
+                .relocate_segments()
+                .unwrap_or_default(),
+            entries,
+        }),
+    })
+}
+
+/// The size of the value `func` returns on success if it returns a `PanicResult`, `None` if it
+/// can't panic.
+fn panic_wrapped_size(builder: &RunnableBuilder, func: &Function) -> Option<i16> {
+    func.signature.ret_types.iter().find_map(|ty| {
+        let long_id = builder.type_long_id(ty);
+        match &long_id.generic_args[..] {
+            [GenericArg::UserType(name), GenericArg::Type(inner), ..]
+                if long_id.generic_id == EnumType::ID
+                    && name
+                        .debug_name
+                        .as_ref()
+                        .is_some_and(|name| name.starts_with("core::panics::PanicResult::")) =>
+            {
+                Some(builder.type_size(inner))
+            }
+            _ => None,
+        }
+    })
+}
+
+/// A [`CairoHintProcessor`] that collects the output of `print` hints into `stdout`.
+struct CapturingHintProcessor<'a> {
+    inner: CairoHintProcessor<'a>,
+    stdout: String,
+}
+
+impl<'a> CapturingHintProcessor<'a> {
+    fn new(
+        runner: &'a SierraCasmRunner,
+        args: Vec<Vec<Arg>>,
+        starknet_state: StarknetState,
+        string_to_hint: HashMap<String, Hint>,
+        max_steps: Option<usize>,
+    ) -> Self {
+        Self {
+            inner: CairoHintProcessor {
+                runner: Some(runner),
+                user_args: args,
+                starknet_state,
+                string_to_hint,
+                run_resources: max_steps.map(RunResources::new).unwrap_or_default(),
+                syscalls_used_resources: Default::default(),
+                no_temporary_segments: true,
+                markers: Default::default(),
+                panic_traceback: Default::default(),
+            },
+            stdout: String::new(),
+        }
+    }
+}
+
+impl HintProcessorLogic for CapturingHintProcessor<'_> {
+    fn execute_hint(
+        &mut self,
+        vm: &mut VirtualMachine,
+        exec_scopes: &mut ExecutionScopes,
+        hint_data: &Box<dyn Any>,
+        constants: &HashMap<String, Felt252>,
+    ) -> Result<(), HintError> {
+        if let Some(Hint::Core(CoreHintBase::Core(CoreHint::DebugPrint { start, end }))) =
+            hint_data.downcast_ref::<Hint>()
+        {
+            let start = extract_relocatable(vm, start)?;
+            let end = extract_relocatable(vm, end)?;
+            self.stdout += &format_for_debug(&vm_get_range(vm, start, end)?);
+            return Ok(());
+        }
+        self.inner
+            .execute_hint(vm, exec_scopes, hint_data, constants)
+    }
+
+    fn compile_hint(
+        &self,
+        hint_code: &str,
+        ap_tracking_data: &ApTracking,
+        reference_ids: &HashMap<String, usize>,
+        references: &[HintReference],
+    ) -> Result<Box<dyn Any>, VirtualMachineError> {
+        self.inner
+            .compile_hint(hint_code, ap_tracking_data, reference_ids, references)
+    }
+}
+
+impl ResourceTracker for CapturingHintProcessor<'_> {
+    fn consumed(&self) -> bool {
+        self.inner.consumed()
+    }
+
+    fn consume_step(&mut self) {
+        self.inner.consume_step()
+    }
+
+    fn get_n_steps(&self) -> Option<usize> {
+        self.inner.get_n_steps()
+    }
+
+    fn run_resources(&self) -> &RunResources {
+        self.inner.run_resources()
+    }
+}
@@ -0,0 +1,137 @@
+use cairo_lang_filesystem::ids::{FileKind, FileLongId, VirtualFile};
+use cairo_lang_formatter::{
+    CollectionsBreakingBehavior, FormatterConfig, cairo_formatter::FileDiff, get_formatted_file,
+};
+use cairo_lang_parser::utils::{SimpleParserDatabase, get_syntax_root_and_diagnostics};
+use cairo_lang_utils::Intern;
+use cairo_runner_types::FormattedCode;
+
+use crate::diagnostics::{CompilationError, parser_diagnostics};
+use crate::error::RunnerError;
+
+/// Options of the formatter, each defaulting to the one of `cairo-format` and `scarb fmt`.
+#[derive(Debug, Clone, Default)]
+pub struct FormatConfig {
+    /// Defaults to 100.
+    pub max_line_length: Option<usize>,
+    /// Defaults to 4.
+    pub tab_size: Option<usize>,
+    /// Sort the `use`s and `mod`s of each module. Defaults to `true`.
+    pub sort_module_level_items: Option<bool>,
+    /// Merge the `use`s of the same path into a single `use`. Defaults to `true`.
+    pub merge_use_items: Option<bool>,
+    /// Return a unified diff from the code to its formatted version instead of the formatted
+    /// code.
+    pub diff: bool,
+}
+
+impl FormatConfig {
+    fn formatter_config(&self) -> FormatterConfig {
+        FormatterConfig::new(
+            self.tab_size.unwrap_or(4),
+            self.max_line_length.unwrap_or(100),
+            self.sort_module_level_items.unwrap_or(true),
+            CollectionsBreakingBehavior::LineByLine,
+            CollectionsBreakingBehavior::SingleBreakPoint,
+            self.merge_use_items.unwrap_or(true),
+            false,
+        )
+    }
+}
+
+/// Formats the code of a `lib.cairo`, failing with a [`RunnerError::Compilation`] if it doesn't
+/// parse.
+pub fn format_cairo_code(code: &str, config: &FormatConfig) -> Result<FormattedCode, RunnerError> {
+    let db = SimpleParserDatabase::default();
+    let file_id = FileLongId::Virtual(VirtualFile {
+        parent: None,
+        name: "lib.cairo".into(),
+        content: code.into(),
+        code_mappings: [].into(),
+        kind: FileKind::Module,
+    })
+    .intern(&db);
+    let (syntax_root, diagnostics) = get_syntax_root_and_diagnostics(&db, file_id, code);
+    if diagnostics.check_error_free().is_err() {
+        return Err(CompilationError {
+            message: diagnostics.format(&db),
+            diagnostics: parser_diagnostics(&db, diagnostics),
+        }
+        .into());
+    }
+
+    let formatted = get_formatted_file(&db, &syntax_root, config.formatter_config());
+    let changed = formatted != code;
+    Ok(if config.diff {
+        let diff = FileDiff {
+            original: code.to_string(),
+            formatted,
+        };
+        FormattedCode {
+            changed,
+            code: None,
+            diff: Some(diff.to_string()),
+        }
+    } else {
+        FormattedCode {
+            changed,
+            code: Some(formatted),
+            diff: None,
+        }
+    })
+}
+
+#[cfg(test)]
+mod formatter_tests {
+    use super::*;
+    use cairo_runner_types::DiagnosticSeverity;
+
+    #[test]
+    fn test_format_code() {
+        let output =
+            format_cairo_code("fn main()->felt252{let x=1;x+2}", &FormatConfig::default()).unwrap();
+        assert!(output.changed);
+        assert_eq!(
+            output.code.unwrap(),
+            "fn main() -> felt252 {\n    let x = 1;\n    x + 2\n}\n"
+        );
+
+        let output = format_cairo_code("fn main() {}\n", &FormatConfig::default()).unwrap();
+        assert!(!output.changed);
+    }
+
+    #[test]
+    fn test_format_options_and_diff() {
+        let code = "fn main() {\n    let x = 1;\n}\n";
+        let config = FormatConfig {
+            tab_size: Some(2),
+            diff: true,
+            ..Default::default()
+        };
+        let output = format_cairo_code(code, &config).unwrap();
+        assert_eq!(output.code, None);
+        let diff = output.diff.unwrap();
+        assert!(diff.contains("-    let x = 1;\n+  let x = 1;\n"));
+
+        let code = "fn main() {\n    let value = (1, 2);\n}\n";
+        let config = FormatConfig {
+            max_line_length: Some(20),
+            ..Default::default()
+        };
+        let output = format_cairo_code(code, &FormatConfig::default()).unwrap();
+        assert!(!output.changed);
+        let output = format_cairo_code(code, &config).unwrap();
+        assert!(output.changed);
+    }
+
+    #[test]
+    fn test_format_parse_error() {
+        let Err(RunnerError::Compilation(error)) =
+            format_cairo_code("fn main( {", &FormatConfig::default())
+        else {
+            panic!("Expected a parse error.");
+        };
+        assert_eq!(error.diagnostics[0].severity, DiagnosticSeverity::Error);
+        assert_eq!(error.diagnostics[0].file, "lib.cairo");
+    }
+}
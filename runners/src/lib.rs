@@ -1,2 +1,16 @@
+pub mod args;
+pub mod backtrace;
+pub mod cache;
+pub mod compiler;
+pub mod corelib;
+pub mod database;
+pub mod decode;
+pub mod diagnostics;
+pub mod error;
+pub mod execution;
+pub mod formatter;
 pub mod main_runner;
+pub mod profiling;
+pub mod sessions;
 pub mod test_runner;
+pub mod trace;
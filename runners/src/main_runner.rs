@@ -1,132 +1,585 @@
-use anyhow::Context;
 use cairo_lang_compiler::{
     db::RootDatabase, diagnostics::DiagnosticsReporter, project::ProjectError,
 };
+use cairo_lang_defs::ids::TopLevelLanguageElementId;
 use cairo_lang_diagnostics::ToOption;
 use cairo_lang_filesystem::{
     db::{CrateConfiguration, FilesGroupEx},
-    ids::{CrateId, Directory},
+    ids::{CrateId, Directory, FileKind, FileLongId, VirtualFile},
 };
-use cairo_lang_formatter::cairo_formatter::FormattableInput;
-use cairo_lang_runner::{casm_run::format_next_item, SierraCasmRunner, StarknetState};
-use cairo_lang_semantic::{db::SemanticGroup, test_utils::get_crate_semantic_diagnostics};
+use cairo_lang_runnable_utils::builder::RunnableBuilder;
+use cairo_lang_runner::{
+    ProfilingInfoCollectionConfig, RunResultValue, SierraCasmRunner, StarknetState,
+};
+use cairo_lang_semantic::db::SemanticGroup;
+use cairo_lang_sierra::program::Program;
 use cairo_lang_sierra_generator::{
     db::SierraGenGroup,
-    program_generator::SierraProgramWithDebug,
+    program_generator::{SierraProgramDebugInfo, SierraProgramWithDebug},
     replace_ids::{DebugReplacer, SierraIdReplacer},
+    statements_locations::StatementsLocations,
+};
+use cairo_lang_sierra_to_casm::metadata::MetadataComputationConfig;
+use cairo_lang_starknet::contract::{find_contracts, get_contracts_info, ContractInfo};
+use cairo_lang_utils::{ordered_hash_map::OrderedHashMap, Intern, Upcast};
+use cairo_runner_types::{
+    CairoRunRequest, CairoRunResponse, Diagnostic, FunctionSignature, MemoryRange, ProfilerKind,
+    RunOutcome, RunStatus, TraceFormat,
 };
-use cairo_lang_starknet::{
-    contract::{find_contracts, get_contracts_info},
-    starknet_plugin_suite,
+use starknet_types_core::felt::Felt as Felt252;
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, LazyLock},
+    time::Duration,
 };
-use cairo_lang_utils::Upcast;
-use std::{collections::BTreeMap, sync::Arc};
 
-const MEMORY_OUTPUT: bool = false;
+use crate::args::{encode_args, function_signatures};
+use crate::backtrace::BacktraceMap;
+use crate::cache::{cache_capacity, cache_key, CacheStats, CompilationCache};
+use crate::database::{DatabaseKind, PooledDatabase};
+use crate::decode::{
+    collect_member_names, decode_panic_data, decode_return_value, felt_to_string, MemberNames,
+};
+use crate::diagnostics::{collect_diagnostics, has_errors, CompilationError};
+use crate::error::RunnerError;
+use crate::execution::{run_function, FunctionRun, RunOptions};
+use crate::profiling::Profiler;
+use crate::trace::{execution_trace, memory_dump};
+
+/// The sources of a Cairo crate, keyed by their path relative to the crate root.
+/// `lib.cairo` is the crate root, and `mod foo;` resolves to `foo.cairo`.
+#[derive(Debug, Clone, Default)]
+pub struct CairoProject {
+    pub files: BTreeMap<String, String>,
+}
+
+impl CairoProject {
+    /// A project with `code` as `lib.cairo`, plus the additional `files`.
+    pub fn new(code: String, files: BTreeMap<String, String>) -> Self {
+        let mut project = Self::from(code);
+        project.files.extend(files);
+        project
+    }
+}
+
+impl From<String> for CairoProject {
+    fn from(code: String) -> Self {
+        Self {
+            files: BTreeMap::from([("lib.cairo".into(), code)]),
+        }
+    }
+}
+
+impl From<BTreeMap<String, String>> for CairoProject {
+    fn from(files: BTreeMap<String, String>) -> Self {
+        Self { files }
+    }
+}
+
+/// Options for running the `main` function of a project.
+#[derive(Debug, Clone, Default)]
+pub struct RunConfig {
+    /// The arguments of `main`, serialized according to its parameter types.
+    /// See [`encode_args`] for the accepted forms.
+    pub args: Vec<serde_json::Value>,
+    /// The suffix of the function to run, e.g. `lib::fib` or `::compute`. Defaults to `::main`.
+    pub entrypoint: Option<String>,
+    /// The gas available to the run. When set, the program is compiled with gas metering and
+    /// running out of gas ends the run with [`RunStatus::OutOfGas`].
+    pub available_gas: Option<usize>,
+    /// The number of CASM steps after which the VM is stopped, ending the run with
+    /// [`RunStatus::StepLimitExceeded`]. Unlimited when `None`.
+    pub max_steps: Option<usize>,
+    /// Profile the run, see [`RunOutcome::profile`].
+    pub profile: Option<ProfilerKind>,
+    /// Return the execution trace of the run, see [`RunOutcome::trace`].
+    pub trace: Option<TraceFormat>,
+    /// Return these cells of the memory, see [`RunOutcome::memory`].
+    pub memory: Option<MemoryRange>,
+}
+
+impl RunConfig {
+    /// The options of a run request, within the limits of the server.
+    pub fn from_request(request: &CairoRunRequest) -> Self {
+        Self {
+            args: request.args.clone().unwrap_or_default(),
+            entrypoint: request.entrypoint.clone(),
+            available_gas: request
+                .available_gas
+                .map(|gas| gas.min(max_available_gas())),
+            max_steps: Some(max_steps()),
+            profile: request.profile,
+            trace: request.trace,
+            memory: request.memory,
+        }
+    }
+}
+
+/// The default cap on the `available_gas` of a request, see [`max_available_gas`].
+pub const DEFAULT_MAX_AVAILABLE_GAS: usize = 1_000_000_000;
+
+/// The largest `available_gas` a server accepts, from the `MAX_AVAILABLE_GAS` environment
+/// variable or [`DEFAULT_MAX_AVAILABLE_GAS`].
+pub fn max_available_gas() -> usize {
+    std::env::var("MAX_AVAILABLE_GAS")
+        .ok()
+        .and_then(|max| max.parse().ok())
+        .unwrap_or(DEFAULT_MAX_AVAILABLE_GAS)
+}
+
+/// The default step limit of a request, see [`max_steps`].
+pub const DEFAULT_MAX_STEPS: usize = 10_000_000;
+
+/// The step limit a server enforces on every run, from the `MAX_STEPS` environment variable or
+/// [`DEFAULT_MAX_STEPS`].
+pub fn max_steps() -> usize {
+    std::env::var("MAX_STEPS")
+        .ok()
+        .and_then(|max| max.parse().ok())
+        .unwrap_or(DEFAULT_MAX_STEPS)
+}
+
+/// The outcome of a run abandoned at the server's deadline.
+pub fn timeout_outcome() -> RunOutcome {
+    RunOutcome::failed(RunStatus::Timeout, String::new(), vec![])
+}
+
+/// The default wall-clock deadline of a request, see [`run_timeout`].
+pub const DEFAULT_RUN_TIMEOUT_MS: u64 = 10_000;
+
+/// The wall-clock deadline a server enforces on every request, from the `RUN_TIMEOUT_MS`
+/// environment variable or [`DEFAULT_RUN_TIMEOUT_MS`].
+pub fn run_timeout() -> Duration {
+    let timeout_ms = std::env::var("RUN_TIMEOUT_MS")
+        .ok()
+        .and_then(|timeout| timeout.parse().ok())
+        .unwrap_or(DEFAULT_RUN_TIMEOUT_MS);
+    Duration::from_millis(timeout_ms)
+}
+
+/// Runs `run` on a blocking thread, answering with a [`timeout_outcome`] if it does not finish
+/// within [`run_timeout`]. An abandoned run keeps its thread until it finishes, which the step
+/// limit bounds for the execution but not for the compilation. A panic of the run is reported as
+/// a [`RunnerError::Internal`].
+pub async fn run_with_timeout(
+    run: impl FnOnce() -> Result<CairoRunResponse, RunnerError> + Send + 'static,
+) -> Result<CairoRunResponse, RunnerError> {
+    match tokio::time::timeout(run_timeout(), tokio::task::spawn_blocking(run)).await {
+        Ok(Ok(response)) => response,
+        Ok(Err(error)) => Err(RunnerError::Internal(format!("Run aborted: {error}"))),
+        Err(_) => {
+            let outcome = timeout_outcome();
+            Ok(CairoRunResponse {
+                message: outcome.to_string(),
+                success: true,
+                outcome: Some(outcome),
+                ..Default::default()
+            })
+        }
+    }
+}
 
 pub fn setup_input_string_project(
     db: &mut dyn SemanticGroup,
     input: String,
 ) -> Result<CrateId, ProjectError> {
+    setup_input_files_project(db, &CairoProject::from(input))
+}
+
+/// Sets up a virtual `lib` crate from the project's files, creating a virtual directory for every
+/// nested path.
+pub fn setup_input_files_project(
+    db: &mut dyn SemanticGroup,
+    project: &CairoProject,
+) -> Result<CrateId, ProjectError> {
+    if !project.files.contains_key("lib.cairo") {
+        return Err(ProjectError::NoSuchFile {
+            path: "lib.cairo".into(),
+        });
+    }
+
+    for path in project.files.keys() {
+        let components: Vec<&str> = path.split('/').collect();
+        if components
+            .iter()
+            .any(|c| c.is_empty() || *c == "." || *c == "..")
+        {
+            return Err(ProjectError::BadPath { path: path.clone() });
+        }
+        if !path.ends_with(".cairo") {
+            return Err(ProjectError::BadFileExtension);
+        }
+    }
+    let root = virtual_directory(
+        db,
+        "",
+        project
+            .files
+            .iter()
+            .map(|(path, content)| (path.as_str(), content.as_str())),
+    );
+
     let crate_id = CrateId::plain(db, "lib");
-    let file_id = FormattableInput::to_file_id(&input, db.as_files_group_mut()).unwrap();
+    db.set_crate_config(crate_id, Some(CrateConfiguration::default_for_root(root)));
+    Ok(crate_id)
+}
 
-    let dir = Directory::Virtual {
-        files: BTreeMap::from([("lib.cairo".into(), file_id)]),
+/// Builds a virtual directory of the files, keyed by their `/`-separated path in it. Each file is
+/// named by its path, after `name_prefix`.
+pub(crate) fn virtual_directory<'a>(
+    db: &dyn SemanticGroup,
+    name_prefix: &str,
+    files: impl IntoIterator<Item = (&'a str, &'a str)>,
+) -> Directory {
+    let mut root = Directory::Virtual {
+        files: BTreeMap::new(),
         dirs: BTreeMap::new(),
     };
+    for (path, content) in files {
+        let file_id = FileLongId::Virtual(VirtualFile {
+            parent: None,
+            name: format!("{name_prefix}{path}").into(),
+            content: content.into(),
+            code_mappings: [].into(),
+            kind: FileKind::Module,
+        })
+        .intern(db);
 
-    db.set_crate_config(crate_id, Some(CrateConfiguration::default_for_root(dir)));
-    Ok(crate_id)
+        let components: Vec<&str> = path.split('/').collect();
+        let (file_name, dir_names) = components.split_last().unwrap();
+        let mut dir = &mut root;
+        for dir_name in dir_names {
+            let Directory::Virtual { dirs, .. } = dir else {
+                unreachable!("Only virtual directories are created.");
+            };
+            dir = dirs.entry((*dir_name).into()).or_insert_with(|| {
+                Box::new(Directory::Virtual {
+                    files: BTreeMap::new(),
+                    dirs: BTreeMap::new(),
+                })
+            });
+        }
+        let Directory::Virtual { files, .. } = dir else {
+            unreachable!("Only virtual directories are created.");
+        };
+        files.insert((*file_name).into(), file_id);
+    }
+    root
 }
 
-pub fn run_cairo_code(code: String) -> anyhow::Result<String> {
-    let mut output = "".into();
-    let mut db_builder = RootDatabase::builder();
-    db_builder.detect_corelib();
-    db_builder.with_default_plugin_suite(starknet_plugin_suite());
-
-    let db = &mut db_builder.build()?;
+/// A compiled project, ready to be run.
+pub struct CompiledProject {
+    pub sierra_program: Program,
+    pub contracts_info: OrderedHashMap<Felt252, ContractInfo>,
+    /// The paths of the contract modules, e.g. `lib::counter`, in the order of `contracts_info`.
+    pub contract_names: Vec<String>,
+    /// Compiler warnings.
+    pub diagnostics: Vec<Diagnostic>,
+    /// The Cairo locations of the Sierra statements.
+    pub statements_locations: StatementsLocations,
+    /// The database the project was compiled in, which resolves the debug info.
+    pub db: PooledDatabase,
+    /// The crate of the project in `db`.
+    pub crate_id: CrateId,
+    /// The names of the members of the project's structs and enums, which decode its values.
+    pub member_names: MemberNames,
+    /// The source locations of the statements of panic backtraces.
+    pub backtrace_map: BacktraceMap,
+}
 
-    let main_crate_id = setup_input_string_project(db, code).unwrap();
+/// Compiles a project into a Sierra program with debug names, failing with a
+/// [`RunnerError::Compilation`] if the code has errors.
+pub fn compile_project(project: &CairoProject) -> Result<CompiledProject, RunnerError> {
+    let mut pooled_db = PooledDatabase::checkout(DatabaseKind::Program)?;
+    let db: &mut RootDatabase = &mut pooled_db;
 
-    let mut reporter = DiagnosticsReporter::stderr().with_crates(&[main_crate_id]);
+    let main_crate_id = setup_input_files_project(db, project)?;
 
-    if reporter.check(db) {
-        let semantic_errors = get_crate_semantic_diagnostics(db, main_crate_id).format(db);
-        anyhow::bail!(semantic_errors);
+    let diagnostics = collect_diagnostics(db, &[main_crate_id]);
+    if has_errors(&diagnostics) {
+        let mut message = String::new();
+        DiagnosticsReporter::write_to_string(&mut message)
+            .with_crates(&[main_crate_id])
+            .allow_warnings()
+            .check(db);
+        return Err(CompilationError {
+            message,
+            diagnostics,
+        }
+        .into());
     }
 
     let SierraProgramWithDebug {
         program: mut sierra_program,
-        debug_info: _,
+        debug_info: SierraProgramDebugInfo {
+            statements_locations,
+        },
     } = Arc::unwrap_or_clone(
-        db.get_sierra_program([main_crate_id.clone()].into())
+        db.get_sierra_program([main_crate_id].into())
             .to_option()
-            .with_context(|| "Compilation failed without any diagnostics.")?,
+            .ok_or_else(|| CompilationError {
+                message: "Compilation failed without any diagnostics.".into(),
+                diagnostics: diagnostics.clone(),
+            })?,
     );
 
     let replacer = DebugReplacer { db };
     replacer.enrich_function_names(&mut sierra_program);
 
     let contracts = find_contracts((*db).upcast(), &[main_crate_id]);
-    let contracts_info = get_contracts_info(db, contracts, &replacer)?;
+    let contract_names = contracts
+        .iter()
+        .map(|contract| contract.submodule_id.full_path((*db).upcast()))
+        .collect();
+    let contracts_info = get_contracts_info(db, contracts, &replacer).map_err(|e| {
+        RunnerError::Compilation(CompilationError {
+            message: e.to_string(),
+            diagnostics: diagnostics.clone(),
+        })
+    })?;
+
     let sierra_program = replacer.apply(&sierra_program);
+    let member_names =
+        collect_member_names(db, &[main_crate_id, CrateId::core(db)], &sierra_program);
+    let backtrace_map = BacktraceMap::new(db, &sierra_program, &statements_locations);
+    Ok(CompiledProject {
+        sierra_program,
+        contracts_info,
+        contract_names,
+        diagnostics,
+        statements_locations,
+        db: pooled_db,
+        crate_id: main_crate_id,
+        member_names,
+        backtrace_map,
+    })
+}
 
-    let runner = SierraCasmRunner::new(sierra_program.clone(), None, contracts_info, None)
-        .with_context(|| "Failed to create Sierra runner.")
-        .unwrap();
+/// The parts of a [`CompiledProject`] needed to run it, which are shared by the runs of the same
+/// sources.
+pub struct CompiledProgram {
+    pub sierra_program: Program,
+    pub contracts_info: OrderedHashMap<Felt252, ContractInfo>,
+    pub contract_names: Vec<String>,
+    /// Compiler warnings.
+    pub diagnostics: Vec<Diagnostic>,
+    pub member_names: MemberNames,
+    pub backtrace_map: BacktraceMap,
+}
 
-    let result = runner
-        .run_function_with_starknet_context(
-            runner.find_function("::main")?,
-            vec![],
-            None,
-            StarknetState::default(),
-        )
-        .with_context(|| "Failed to run the function.")
-        .unwrap();
+impl CompiledProject {
+    /// Splits the project into its program and the database and locations resolving its debug
+    /// info.
+    fn into_parts(self) -> (CompiledProgram, PooledDatabase, StatementsLocations) {
+        let program = CompiledProgram {
+            sierra_program: self.sierra_program,
+            contracts_info: self.contracts_info,
+            contract_names: self.contract_names,
+            diagnostics: self.diagnostics,
+            member_names: self.member_names,
+            backtrace_map: self.backtrace_map,
+        };
+        (program, self.db, self.statements_locations)
+    }
+}
 
-    match result.value {
-        cairo_lang_runner::RunResultValue::Success(values) => {
-            output += format!("Run completed successfully, returning {values:?}\n").as_str();
-        }
-        cairo_lang_runner::RunResultValue::Panic(values) => {
-            output += format!("Run panicked with [").as_str();
-            let mut felts = values.into_iter();
-            let mut first = true;
-            while let Some(item) = format_next_item(&mut felts) {
-                if !first {
-                    output += format!(", ").as_str();
-                }
-                first = false;
-                output += format!("{}", item.quote_if_string()).as_str();
-            }
-            output += format!("].\n").as_str();
-        }
+impl From<CompiledProject> for CompiledProgram {
+    fn from(compiled: CompiledProject) -> Self {
+        compiled.into_parts().0
     }
+}
 
-    if MEMORY_OUTPUT {
-        output += format!("Full memory: [").as_str();
-        for cell in &result.memory {
-            match cell {
-                None => output += format!("_, ").as_str(),
-                Some(value) => output += format!("{value}, ").as_str(),
-            }
+static PROGRAMS: LazyLock<CompilationCache<CompiledProgram>> =
+    LazyLock::new(|| CompilationCache::new(cache_capacity()));
+
+/// Compiles a project like [`compile_project`], reusing the program of a previous compilation of
+/// the same sources.
+pub fn compile_program(project: &CairoProject) -> Result<Arc<CompiledProgram>, RunnerError> {
+    PROGRAMS.get_or_try_insert(cache_key("program", project), || {
+        Ok(compile_project(project)?.into())
+    })
+}
+
+/// The hits and misses of [`compile_program`].
+pub fn program_cache_stats() -> CacheStats {
+    PROGRAMS.stats()
+}
+
+/// Lists the signatures of the functions defined in the project, any of which can be used as an
+/// entrypoint.
+pub fn list_functions(
+    project: impl Into<CairoProject>,
+) -> Result<Vec<FunctionSignature>, RunnerError> {
+    let compiled = compile_program(&project.into())?;
+    function_signatures(&compiled.sierra_program, "lib::")
+        .map_err(|e| RunnerError::Internal(e.to_string()))
+}
+
+pub fn run_cairo_code(
+    project: impl Into<CairoProject>,
+    config: &RunConfig,
+) -> Result<RunOutcome, RunnerError> {
+    let project = project.into();
+    // The profiler resolves names in the database of the compilation, which is not cached.
+    let (program, profiled) = match config.profile {
+        Some(kind) => {
+            let (program, db, statements_locations) = compile_project(&project)?.into_parts();
+            (Arc::new(program), Some((kind, db, statements_locations)))
         }
-        output += format!("]").as_str();
+        None => (compile_program(&project)?, None),
+    };
+    run_program(&program, profiled, config, &mut StarknetState::default())
+}
+
+/// A program compiled for profiling, with the database that resolves its debug info.
+type ProfiledProgram = (ProfilerKind, PooledDatabase, StatementsLocations);
+
+/// Runs a compiled program like [`run_cairo_code`], from `starknet_state`. A successful run
+/// updates `starknet_state` to the state it ended with, as a panic reverts its changes.
+pub(crate) fn run_program(
+    program: &CompiledProgram,
+    profiled: Option<ProfiledProgram>,
+    config: &RunConfig,
+    starknet_state: &mut StarknetState,
+) -> Result<RunOutcome, RunnerError> {
+    let sierra_program = &program.sierra_program;
+    let diagnostics = program.diagnostics.clone();
+
+    let metadata_config = || {
+        config
+            .available_gas
+            .map(|_| MetadataComputationConfig::default())
+    };
+    let runner = SierraCasmRunner::new(
+        sierra_program.clone(),
+        metadata_config(),
+        program.contracts_info.clone(),
+        None,
+    )
+    .map_err(|e| RunnerError::RunnerSetup(format!("Failed to create Sierra runner: {e}")))?;
+    let builder = RunnableBuilder::new(sierra_program.clone(), metadata_config())
+        .map_err(|e| RunnerError::RunnerSetup(format!("Failed to create Sierra runner: {e}")))?;
+
+    let func = runner
+        .find_function(config.entrypoint.as_deref().unwrap_or("::main"))
+        .map_err(|e| RunnerError::EntrypointNotFound(e.to_string()))?;
+    let args = encode_args(sierra_program, func, &config.args)
+        .map_err(|e| RunnerError::RunnerSetup(e.to_string()))?;
+    if let (Some(available_gas), Some(required_gas)) =
+        (config.available_gas, runner.initial_required_gas(func))
+        && available_gas < required_gas
+    {
+        return Ok(RunOutcome::failed(
+            RunStatus::OutOfGas,
+            String::new(),
+            diagnostics,
+        ));
     }
+    let FunctionRun {
+        result,
+        stdout,
+        panic_statements,
+        out_of_gas,
+        trace,
+    } = run_function(
+        &runner,
+        &builder,
+        func,
+        args,
+        RunOptions {
+            available_gas: config.available_gas,
+            max_steps: config.max_steps,
+            // The segments of the memory are only known from a traced run.
+            trace: config.trace.is_some()
+                || config.memory.is_some_and(|range| range.segment.is_some()),
+            profiling: config
+                .profile
+                .map(|_| ProfilingInfoCollectionConfig::default()),
+            starknet_state: starknet_state.clone(),
+        },
+    )?;
+    let Some(result) = result else {
+        return Ok(RunOutcome {
+            steps: config.max_steps.unwrap_or_default(),
+            ..RunOutcome::failed(RunStatus::StepLimitExceeded, stdout, diagnostics)
+        });
+    };
 
-    Ok(output)
+    let steps = result.used_resources.basic_resources.n_steps;
+    let gas_used = config
+        .available_gas
+        .zip(result.gas_counter)
+        .map(|(available_gas, counter)| {
+            available_gas as u64 - u64::try_from(counter).unwrap_or_default()
+        })
+        .or_else(|| runner.initial_required_gas(func).map(|gas| gas as u64));
+    let profile = match (&profiled, &result.profiling_info) {
+        (Some((kind, db, statements_locations)), Some(profiling_info)) => {
+            let db: &RootDatabase = db;
+            let statements_functions =
+                statements_locations.get_statements_functions_map_for_tests(db);
+            let profiler = Profiler::new(*kind, db, sierra_program.clone(), statements_functions);
+            Some(profiler.profile(profiling_info))
+        }
+        _ => None,
+    };
+    let memory = config.memory.map(|range| {
+        let segments = trace.as_ref().map_or(&[][..], |trace| &trace.segments);
+        memory_dump(&range, &result.memory, segments)
+    });
+    let trace = config
+        .trace
+        .zip(trace)
+        .map(|(format, trace)| execution_trace(format, &trace.entries, &result.memory));
+    if let RunResultValue::Success(_) = result.value {
+        *starknet_state = result.starknet_state;
+    }
+    let outcome = RunOutcome {
+        gas_used,
+        steps,
+        profile,
+        trace,
+        memory,
+        ..RunOutcome::failed(RunStatus::Panic, stdout, diagnostics)
+    };
+    Ok(match result.value {
+        RunResultValue::Success(values) => RunOutcome {
+            status: RunStatus::Success,
+            values: values.iter().map(felt_to_string).collect(),
+            // Types that are not decoded still have their felts in `values`.
+            value: decode_return_value(
+                sierra_program,
+                func,
+                &values,
+                &result.memory,
+                &program.member_names,
+            )
+            .unwrap_or_default(),
+            ..outcome
+        },
+        RunResultValue::Panic(values) => RunOutcome {
+            status: if out_of_gas {
+                RunStatus::OutOfGas
+            } else {
+                RunStatus::Panic
+            },
+            panic_data: decode_panic_data(&values),
+            backtrace: program
+                .backtrace_map
+                .backtrace(sierra_program, &panic_statements),
+            ..outcome
+        },
+    })
 }
 
 #[cfg(test)]
 mod runner_tests {
     use super::*;
+    use cairo_runner_types::{DiagnosticSeverity, PanicItem};
 
     pub fn run_cairo_code_string_output(code: String) -> String {
-        match run_cairo_code(code) {
-            Ok(output) => output,
+        match run_cairo_code(code, &RunConfig::default()) {
+            Ok(outcome) => outcome.to_string(),
             Err(e) => format!("Error: {}", e),
         }
     }
@@ -158,13 +611,87 @@ fn main(){// this is some Cairo code
                 4 + 5;
             }
         "#;
-        let output = match run_cairo_code(code.to_string()) {
+        let output = match run_cairo_code(code.to_string(), &RunConfig::default()) {
             Ok(_) => panic!("output should have error"),
             Err(e) => format!("{}", e),
         };
         assert!(output.contains("Unexpected return type."));
     }
 
+    #[test]
+    fn test_cairo_code_multiple_files() {
+        let files = BTreeMap::from([
+            (
+                "lib.cairo".to_string(),
+                "mod foo;\nfn main() -> felt252 { foo::bar::value() }".to_string(),
+            ),
+            ("foo.cairo".to_string(), "pub mod bar;".to_string()),
+            (
+                "foo/bar.cairo".to_string(),
+                "pub fn value() -> felt252 { 7 }".to_string(),
+            ),
+        ]);
+        let outcome = run_cairo_code(files, &RunConfig::default()).unwrap();
+        assert_eq!(outcome.values, vec!["7".to_string()]);
+    }
+
+    #[test]
+    fn test_cairo_code_nested_file_diagnostics() {
+        let files = BTreeMap::from([
+            (
+                "lib.cairo".to_string(),
+                "mod foo;\nfn main() {}".to_string(),
+            ),
+            (
+                "foo.cairo".to_string(),
+                "fn broken() -> felt252 {}".to_string(),
+            ),
+        ]);
+        let error = run_cairo_code(files, &RunConfig::default()).unwrap_err();
+        let RunnerError::Compilation(error) = error else {
+            panic!("Expected a compilation error, got {error}");
+        };
+        assert_eq!(error.diagnostics[0].file, "foo.cairo");
+    }
+
+    #[test]
+    fn test_cairo_code_missing_lib() {
+        let files = BTreeMap::from([("foo.cairo".to_string(), "fn main() {}".to_string())]);
+        assert!(matches!(
+            run_cairo_code(files, &RunConfig::default()),
+            Err(RunnerError::Compilation(_))
+        ));
+    }
+
+    #[test]
+    fn test_cairo_code_diagnostics() {
+        let code = "fn main() -> felt252 {\n    4 + 5;\n}\n";
+        let error = run_cairo_code(code.to_string(), &RunConfig::default()).unwrap_err();
+        let RunnerError::Compilation(error) = error else {
+            panic!("Expected a compilation error, got {error}");
+        };
+        let diagnostic = &error.diagnostics[0];
+        assert_eq!(diagnostic.severity, DiagnosticSeverity::Error);
+        assert_eq!(diagnostic.file, "lib.cairo");
+        assert!(diagnostic.message.contains("Unexpected return type."));
+        assert_eq!(diagnostic.start.0, 0);
+    }
+
+    #[test]
+    fn test_cairo_code_warnings() {
+        let code = r#"
+            fn main() -> felt252 {
+                let unused = 1;
+                42
+            }
+        "#;
+        let outcome = run_cairo_code(code.to_string(), &RunConfig::default()).unwrap();
+        assert_eq!(outcome.status, RunStatus::Success);
+        assert_eq!(outcome.diagnostics.len(), 1);
+        assert_eq!(outcome.diagnostics[0].severity, DiagnosticSeverity::Warning);
+        assert_eq!(outcome.diagnostics[0].start, (2, 20));
+    }
+
     #[test]
     fn test_cairo_code_panic() {
         let code = r#"
@@ -176,4 +703,533 @@ fn main(){// this is some Cairo code
         assert!(output.contains("Run panicked with"));
         assert!(output.contains("good_error_has_occurred"));
     }
+
+    #[test]
+    fn test_cairo_code_outcome() {
+        let code = r#"
+            fn main() -> felt252 {
+                return 42;
+            }
+        "#;
+        let outcome = run_cairo_code(code.to_string(), &RunConfig::default()).unwrap();
+        assert_eq!(outcome.status, RunStatus::Success);
+        assert_eq!(outcome.values, vec!["42".to_string()]);
+        assert!(outcome.panic_data.is_empty());
+        assert!(outcome.steps > 0);
+    }
+
+    #[test]
+    fn test_cairo_code_panic_outcome() {
+        let code = r#"
+            fn main() {
+                let mut data = array!['short', core::byte_array::BYTE_ARRAY_MAGIC];
+                let message: ByteArray = "long message";
+                message.serialize(ref data);
+                panic(data);
+            }
+        "#;
+        let outcome = run_cairo_code(code.to_string(), &RunConfig::default()).unwrap();
+        assert_eq!(outcome.status, RunStatus::Panic);
+        assert_eq!(
+            outcome.panic_data,
+            vec![
+                PanicItem::Felt {
+                    value: "0x73686f7274".into(),
+                    short_string: Some("short".into()),
+                },
+                PanicItem::String {
+                    value: "long message".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cairo_code_panic_backtrace() {
+        let code = r#"
+fn main() {
+    helper(3);
+}
+
+fn helper(x: u32) {
+    assert(x == 2, 'x is not 2');
+}
+"#;
+        let outcome = run_cairo_code(code.to_string(), &RunConfig::default()).unwrap();
+        assert_eq!(outcome.status, RunStatus::Panic);
+        let position = |function: &str| {
+            outcome
+                .backtrace
+                .iter()
+                .position(|frame| frame.function == function)
+                .unwrap()
+        };
+        let (helper, main) = (position("lib::helper"), position("lib::main"));
+        assert!(helper < main);
+        let main_location = outcome.backtrace[main].location.as_ref().unwrap();
+        assert_eq!(main_location.file, "lib.cairo");
+        assert_eq!(main_location.line, 2);
+        let output = outcome.to_string();
+        assert!(output.contains("    at lib::main (lib.cairo:3:"));
+
+        let code = "fn main() -> u32 { 1 }";
+        let outcome = run_cairo_code(code.to_string(), &RunConfig::default()).unwrap();
+        assert!(outcome.backtrace.is_empty());
+    }
+
+    fn run_with_args(code: &str, args: serde_json::Value) -> Result<RunOutcome, RunnerError> {
+        let config = RunConfig {
+            args: serde_json::from_value(args).unwrap(),
+            ..Default::default()
+        };
+        run_cairo_code(code.to_string(), &config)
+    }
+
+    #[test]
+    fn test_cairo_code_felt_args() {
+        let code = "fn main(a: felt252, b: u32, c: i8) -> felt252 { a + b.into() + c.into() }";
+        let outcome = run_with_args(code, serde_json::json!([40, "0x2", "-3"])).unwrap();
+        assert_eq!(outcome.values, vec!["39".to_string()]);
+    }
+
+    #[test]
+    fn test_cairo_code_u256_arg() {
+        let code = "fn main(a: u256, b: bool) -> (u128, u128, bool) { (a.low, a.high, !b) }";
+        let outcome = run_with_args(
+            code,
+            serde_json::json!(["340282366920938463463374607431768211457", true]),
+        )
+        .unwrap();
+        assert_eq!(outcome.values, vec!["1", "1", "0"]);
+    }
+
+    #[test]
+    fn test_cairo_code_array_args() {
+        let code = r#"
+            fn main(a: Array<u256>, b: Span<Array<felt252>>) -> (usize, usize, u256) {
+                (a.len(), b.at(1).len(), *a.at(1))
+            }
+        "#;
+        let outcome = run_with_args(code, serde_json::json!([[1, 2], [[], [3, 4, 5]]])).unwrap();
+        assert_eq!(outcome.values, vec!["2", "3", "2", "0"]);
+    }
+
+    #[test]
+    fn test_cairo_code_byte_array_arg() {
+        let code = r#"
+            #[derive(Drop)]
+            struct Point { x: felt252, y: felt252 }
+            fn main(s: ByteArray, p: Point) -> (usize, felt252) {
+                assert(s == "a string which is longer than a single word", 'wrong string');
+                (s.len(), p.x + p.y)
+            }
+        "#;
+        let outcome = run_with_args(
+            code,
+            serde_json::json!(["a string which is longer than a single word", [1, 2]]),
+        )
+        .unwrap();
+        assert_eq!(outcome.status, RunStatus::Success);
+        assert_eq!(outcome.values, vec!["43", "3"]);
+    }
+
+    #[test]
+    fn test_cairo_code_invalid_args() {
+        let code = "fn main(a: felt252) -> felt252 { a }";
+        let error = run_with_args(code, serde_json::json!([])).unwrap_err();
+        assert!(matches!(error, RunnerError::RunnerSetup(_)));
+        assert_eq!(error.to_string(), "Expected 1 arguments, got 0.");
+        let error = run_with_args(code, serde_json::json!([[1]])).unwrap_err();
+        assert!(error.to_string().contains("Invalid argument 0"));
+    }
+
+    #[test]
+    fn test_cairo_code_out_of_range_args() {
+        let code = "fn main(a: u8, b: i8) -> felt252 { a.into() + b.into() }";
+        let outcome = run_with_args(code, serde_json::json!([255, -128])).unwrap();
+        assert_eq!(outcome.values, vec!["127".to_string()]);
+        let error = run_with_args(code, serde_json::json!([-1, 0])).unwrap_err();
+        assert!(matches!(error, RunnerError::RunnerSetup(_)));
+        assert!(
+            error
+                .to_string()
+                .contains("out of the range of `u8`, [0, 255]")
+        );
+        let error = run_with_args(code, serde_json::json!([256, 0])).unwrap_err();
+        assert!(matches!(error, RunnerError::RunnerSetup(_)));
+        let error = run_with_args(code, serde_json::json!([0, "128"])).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("out of the range of `i8`, [-128, 127]")
+        );
+        let code = "fn main(a: u128) -> u128 { a }";
+        let error = run_with_args(
+            code,
+            serde_json::json!(["0x100000000000000000000000000000000"]),
+        )
+        .unwrap_err();
+        assert!(matches!(error, RunnerError::RunnerSetup(_)));
+    }
+
+    #[test]
+    fn test_cairo_code_decoded_value() {
+        let code = r#"
+            #[derive(Drop)]
+            struct Point { x: i16, y: u256 }
+            #[derive(Drop)]
+            enum Shape { Empty, Dot: Point, Segment: (Point, Point) }
+            fn main() -> (u8, i64, felt252, bool, Point, Shape, Shape) {
+                let p = Point { x: -3, y: 0x100000000000000000000000000000001 };
+                (7, -2, 'hi', true, Point { x: 1, y: 2 }, Shape::Dot(p), Shape::Empty)
+            }
+        "#;
+        let outcome = run_cairo_code(code.to_string(), &RunConfig::default()).unwrap();
+        let point = serde_json::json!({"x": -3, "y": "340282366920938463463374607431768211457"});
+        assert_eq!(
+            outcome.value,
+            Some(serde_json::json!([
+                7,
+                -2,
+                "26729",
+                true,
+                {"x": 1, "y": "2"},
+                {"Dot": point},
+                "Empty"
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_cairo_code_decoded_collections() {
+        let code = r#"
+            fn main() -> (Array<u32>, Span<ByteArray>, Option<u8>, Option<u8>, Result<u8, felt252>) {
+                let strings = array!["a string which is longer than a single word", ""];
+                (array![1, 2, 3], strings.span(), Option::Some(4), Option::None, Result::Err('no'))
+            }
+        "#;
+        let outcome = run_cairo_code(code.to_string(), &RunConfig::default()).unwrap();
+        assert_eq!(
+            outcome.value,
+            Some(serde_json::json!([
+                [1, 2, 3],
+                ["a string which is longer than a single word", ""],
+                {"Some": 4},
+                "None",
+                {"Err": "28271"}
+            ]))
+        );
+        assert_eq!(
+            outcome.to_string(),
+            "Run completed successfully, returning [[1,2,3],[\"a string which is longer than a \
+             single word\",\"\"],{\"Some\":4},\"None\",{\"Err\":\"28271\"}]\n"
+        );
+
+        // Types that are not decoded only have their felts.
+        let code = "fn main() -> Nullable<u8> { core::nullable::NullableTrait::new(1) }";
+        let outcome = run_cairo_code(code.to_string(), &RunConfig::default()).unwrap();
+        assert_eq!(outcome.value, None);
+        assert_eq!(outcome.values.len(), 1);
+    }
+
+    #[test]
+    fn test_cairo_code_entrypoint() {
+        let code = r#"
+            fn main() -> felt252 { fib(10) }
+            fn fib(n: felt252) -> felt252 {
+                if n == 0 || n == 1 { n } else { fib(n - 1) + fib(n - 2) }
+            }
+        "#;
+        let config = RunConfig {
+            args: vec![6.into()],
+            entrypoint: Some("lib::fib".into()),
+            ..Default::default()
+        };
+        let outcome = run_cairo_code(code.to_string(), &config).unwrap();
+        assert_eq!(outcome.values, vec!["8".to_string()]);
+
+        let config = RunConfig {
+            entrypoint: Some("::missing".into()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            run_cairo_code(code.to_string(), &config),
+            Err(RunnerError::EntrypointNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_list_functions() {
+        let code = r#"
+            fn main() {}
+            fn add(a: u256, b: Span<felt252>) -> u256 { a + b.len().into() }
+        "#;
+        let functions = list_functions(code.to_string()).unwrap();
+        let names: Vec<&str> = functions.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["lib::main", "lib::add"]);
+        assert_eq!(functions[0].params, Vec::<String>::new());
+        assert!(!functions[0].panics);
+        assert_eq!(
+            functions[1].params,
+            vec!["core::integer::u256", "core::array::Span::<core::felt252>"]
+        );
+        assert_eq!(functions[1].returns, vec!["core::integer::u256"]);
+        assert!(functions[1].panics);
+    }
+
+    #[test]
+    fn test_cairo_code_gas() {
+        let code = r#"
+            fn main(n: felt252) -> felt252 {
+                let mut i = 0;
+                while i != n { i += 1; };
+                i
+            }
+        "#;
+        let config = |n: u64, available_gas| RunConfig {
+            args: vec![n.into()],
+            available_gas: Some(available_gas),
+            ..Default::default()
+        };
+
+        let short = run_cairo_code(code.to_string(), &config(10, 1_000_000)).unwrap();
+        assert_eq!(short.status, RunStatus::Success);
+        let long = run_cairo_code(code.to_string(), &config(100, 1_000_000)).unwrap();
+        assert_eq!(long.values, vec!["100".to_string()]);
+        assert!(short.gas_used.unwrap() > 0);
+        assert!(long.gas_used.unwrap() > short.gas_used.unwrap());
+
+        let outcome = run_cairo_code(code.to_string(), &config(1_000_000, 100_000)).unwrap();
+        assert_eq!(outcome.status, RunStatus::OutOfGas);
+        assert_eq!(outcome.to_string(), "Run failed: out of gas.\n");
+
+        let outcome = run_cairo_code(code.to_string(), &config(10, 0)).unwrap();
+        assert_eq!(outcome.status, RunStatus::OutOfGas);
+    }
+
+    #[test]
+    fn test_cairo_code_out_of_gas_panic_data() {
+        // The code's own panic is not mistaken for running out of gas, even after a loop
+        // withdrew gas.
+        let code = r#"
+            fn main(n: felt252) {
+                let mut i = 0;
+                while i != n { i += 1; };
+                panic_with_felt252('Out of gas');
+            }
+        "#;
+        for available_gas in [None, Some(1_000_000)] {
+            let config = RunConfig {
+                args: vec![10.into()],
+                available_gas,
+                ..Default::default()
+            };
+            let outcome = run_cairo_code(code.to_string(), &config).unwrap();
+            assert_eq!(outcome.status, RunStatus::Panic);
+            assert_eq!(
+                outcome.panic_data,
+                vec![PanicItem::Felt {
+                    value: "0x4f7574206f6620676173".into(),
+                    short_string: Some("Out of gas".into()),
+                }]
+            );
+        }
+    }
+
+    #[test]
+    fn test_cairo_code_stdout() {
+        let code = r#"
+            fn main() -> u8 {
+                println!("hello {}", 1);
+                print!("no ");
+                println!("newline");
+                4
+            }
+        "#;
+        let outcome = run_cairo_code(code.to_string(), &RunConfig::default()).unwrap();
+        assert_eq!(outcome.values, vec!["4".to_string()]);
+        assert_eq!(outcome.stdout, "hello 1\nno newline\n");
+    }
+
+    #[test]
+    fn test_cairo_code_profile() {
+        let code = r#"
+            fn fib(n: u32) -> u32 {
+                if n < 2 { n } else { fib(n - 1) + fib(n - 2) }
+            }
+            fn main() -> u32 {
+                fib(10)
+            }
+        "#;
+        let outcome = run_cairo_code(code.to_string(), &RunConfig::default()).unwrap();
+        assert_eq!(outcome.profile, None);
+
+        for kind in [ProfilerKind::Sierra, ProfilerKind::Cairo] {
+            let config = RunConfig {
+                profile: Some(kind),
+                ..Default::default()
+            };
+            let outcome = run_cairo_code(code.to_string(), &config).unwrap();
+            let profile = outcome.profile.unwrap();
+            assert!(profile.functions.iter().any(|f| f.function == "lib::fib"));
+            assert!(!profile.statements.is_empty());
+            assert!(
+                profile
+                    .collapsed_stacks
+                    .contains("lib::main;lib::fib;lib::fib ")
+            );
+        }
+    }
+
+    #[test]
+    fn test_cairo_code_trace() {
+        use base64::{Engine, engine::general_purpose::STANDARD};
+        use cairo_runner_types::ExecutionTrace;
+
+        let code = "fn main() -> felt252 { 40 + 2 }";
+        let outcome = run_cairo_code(code.to_string(), &RunConfig::default()).unwrap();
+        assert_eq!(outcome.trace, None);
+
+        let run = |format| {
+            let config = RunConfig {
+                trace: Some(format),
+                ..Default::default()
+            };
+            run_cairo_code(code.to_string(), &config).unwrap()
+        };
+        let outcome = run(TraceFormat::Json);
+        let Some(ExecutionTrace::Json { trace, memory }) = outcome.trace else {
+            panic!("Expected a JSON trace");
+        };
+        // The trace also holds the steps of the entry code before its call into the program.
+        assert!(trace.len() >= outcome.steps);
+        assert!(memory.iter().any(|cell| cell.value == "42"));
+
+        let Some(ExecutionTrace::Binary {
+            trace: trace_bin,
+            memory: memory_bin,
+        }) = run(TraceFormat::Binary).trace
+        else {
+            panic!("Expected a binary trace");
+        };
+        assert_eq!(STANDARD.decode(trace_bin).unwrap().len(), trace.len() * 24);
+        assert_eq!(
+            STANDARD.decode(memory_bin).unwrap().len(),
+            memory.len() * 40
+        );
+    }
+
+    #[test]
+    fn test_cairo_code_memory() {
+        let code = "fn main() -> felt252 { 40 + 2 }";
+        let run = |range| {
+            let config = RunConfig {
+                memory: Some(range),
+                ..Default::default()
+            };
+            run_cairo_code(code.to_string(), &config)
+                .unwrap()
+                .memory
+                .unwrap()
+        };
+
+        // The relocated memory starts at 1.
+        let memory = run(MemoryRange {
+            segment: None,
+            start: 0,
+            length: Some(3),
+        });
+        assert_eq!(memory.cells.len(), 3);
+        assert_eq!(memory.cells[0], None);
+        assert!(memory.cells[1].is_some());
+
+        let program = run(MemoryRange {
+            segment: Some(0),
+            ..Default::default()
+        });
+        assert_eq!(program.address, 1);
+        assert!(program.cells.iter().all(Option::is_some));
+
+        let execution = run(MemoryRange {
+            segment: Some(1),
+            ..Default::default()
+        });
+        assert_eq!(execution.address, 1 + program.cells.len());
+        assert!(execution.cells.contains(&Some("42".into())));
+
+        let missing = run(MemoryRange {
+            segment: Some(100),
+            ..Default::default()
+        });
+        assert!(missing.cells.is_empty());
+    }
+
+    #[test]
+    fn test_cairo_code_step_limit() {
+        let config = RunConfig {
+            max_steps: Some(10_000),
+            ..Default::default()
+        };
+        let outcome = run_cairo_code("fn main() { loop {} }".to_string(), &config).unwrap();
+        assert_eq!(outcome.status, RunStatus::StepLimitExceeded);
+        assert_eq!(outcome.to_string(), "Run failed: step limit exceeded.\n");
+
+        let outcome = run_cairo_code("fn main() -> u8 { 4 }".to_string(), &config).unwrap();
+        assert_eq!(outcome.values, vec!["4".to_string()]);
+        assert!(outcome.steps < 10_000);
+    }
+
+    #[test]
+    fn test_cairo_code_unmetered() {
+        let outcome = run_cairo_code_string_output("fn main() -> u8 { 4 }".to_string());
+        assert_eq!(outcome, "Run completed successfully, returning 4\n");
+        let outcome = run_cairo_code("fn main() {}".to_string(), &RunConfig::default()).unwrap();
+        assert_eq!(outcome.gas_used, None);
+    }
+
+    #[test]
+    fn test_cairo_code_cached() {
+        let code = r#"
+            fn main(x: felt252) -> felt252 {
+                let unused = 1;
+                x * 2
+            }
+        "#;
+        let run = |arg: u32| {
+            let config = RunConfig {
+                args: vec![arg.into()],
+                ..Default::default()
+            };
+            run_cairo_code(code.to_string(), &config).unwrap()
+        };
+        let hits = program_cache_stats().hits;
+        assert_eq!(run(1).values, ["2"]);
+        let outcome = run(21);
+        assert_eq!(outcome.values, ["42"]);
+        assert_eq!(outcome.diagnostics.len(), 1);
+        assert!(program_cache_stats().hits > hits);
+    }
+
+    #[test]
+    fn test_cairo_code_reused_database() {
+        let project = |files: &[(&str, &str)]| {
+            files
+                .iter()
+                .map(|(path, code)| (path.to_string(), code.to_string()))
+                .collect::<BTreeMap<_, _>>()
+        };
+        for _ in 0..2 {
+            let files = project(&[
+                ("lib.cairo", "mod foo;\nfn main() -> u8 { foo::value() }"),
+                ("foo.cairo", "pub fn value() -> u8 { 3 }"),
+            ]);
+            let outcome = run_cairo_code(files, &RunConfig::default()).unwrap();
+            assert_eq!(outcome.values, ["3"]);
+
+            // The files of the previous project are gone from the database.
+            let files = project(&[("lib.cairo", "mod foo;\nfn main() {}")]);
+            let error = run_cairo_code(files, &RunConfig::default()).unwrap_err();
+            assert!(matches!(error, RunnerError::Compilation(_)));
+        }
+    }
 }
@@ -0,0 +1,182 @@
+use cairo_lang_runnable_utils::builder::RunnableBuilder;
+use cairo_lang_runner::ProfilingInfoCollectionConfig;
+use cairo_lang_runner::profiling::{
+    ProfilingInfo, ProfilingInfoProcessor, ProfilingInfoProcessorParams,
+    user_function_idx_by_sierra_statement_idx,
+};
+use cairo_lang_sierra::extensions::core::CoreConcreteLibfunc;
+use cairo_lang_sierra::program::{Program, Statement, StatementIdx};
+use cairo_lang_sierra_generator::db::SierraGenGroup;
+use cairo_lang_utils::{ordered_hash_map::OrderedHashMap, unordered_hash_map::UnorderedHashMap};
+use cairo_runner_types::{FunctionWeight, Profile, ProfilerKind, StatementWeight};
+use cairo_vm::vm::trace::trace_entry::RelocatedTraceEntry;
+
+/// Turns the raw profiling info collected by the runner into a [`Profile`].
+pub struct Profiler<'a> {
+    kind: ProfilerKind,
+    processor: ProfilingInfoProcessor<'a>,
+}
+
+impl<'a> Profiler<'a> {
+    /// `sierra_program` must be the program that ran, and `statements_functions` map its
+    /// statements to the Cairo functions they were generated from.
+    pub fn new(
+        kind: ProfilerKind,
+        db: &'a dyn SierraGenGroup,
+        sierra_program: Program,
+        statements_functions: UnorderedHashMap<StatementIdx, String>,
+    ) -> Self {
+        let sierra = kind == ProfilerKind::Sierra;
+        let params = ProfilingInfoProcessorParams {
+            min_weight: 1,
+            process_by_statement: true,
+            process_by_concrete_libfunc: false,
+            process_by_generic_libfunc: false,
+            process_by_user_function: sierra,
+            process_by_original_user_function: false,
+            process_by_cairo_function: !sierra,
+            process_by_stack_trace: sierra,
+            process_by_cairo_stack_trace: !sierra,
+            process_by_scoped_statement: false,
+        };
+        Self {
+            kind,
+            processor: ProfilingInfoProcessor::new(
+                Some(db),
+                sierra_program,
+                statements_functions,
+                params,
+            ),
+        }
+    }
+
+    pub fn profile(&self, profiling_info: &ProfilingInfo) -> Profile {
+        let processed = self.processor.process(profiling_info);
+        let (functions, stack_traces) = match self.kind {
+            ProfilerKind::Sierra => (
+                processed
+                    .user_function_weights
+                    .user_function_weights
+                    .map(|weights| {
+                        weights
+                            .into_iter()
+                            .map(|(name, weight)| (name.to_string(), weight))
+                            .collect::<OrderedHashMap<_, _>>()
+                    }),
+                processed.stack_trace_weights.sierra_stack_trace_weights,
+            ),
+            ProfilerKind::Cairo => (
+                processed.cairo_function_weights,
+                processed.stack_trace_weights.cairo_stack_trace_weights,
+            ),
+        };
+        Profile {
+            functions: functions
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(function, weight)| FunctionWeight { function, weight })
+                .collect(),
+            statements: processed
+                .sierra_statement_weights
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(idx, (weight, statement))| StatementWeight {
+                    index: idx.0,
+                    statement: statement.to_string(),
+                    weight,
+                })
+                .collect(),
+            collapsed_stacks: collapsed_stacks(&stack_traces.unwrap_or_default()),
+        }
+    }
+}
+
+/// Formats stack trace weights as collapsed stacks, e.g. `lib::main;lib::fib 120`.
+fn collapsed_stacks(weights: &OrderedHashMap<Vec<String>, usize>) -> String {
+    weights
+        .iter()
+        .map(|(stack, weight)| format!("{} {weight}\n", stack.join(";")))
+        .collect()
+}
+
+/// Collects the profiling info of a run from its VM trace, like the runner does for the runs it
+/// makes itself. The header and footer around the program are not counted.
+pub fn collect_profiling_info(
+    builder: &RunnableBuilder,
+    trace: &[RelocatedTraceEntry],
+    config: &ProfilingInfoCollectionConfig,
+) -> ProfilingInfo {
+    let program = builder.sierra_program();
+    let statement_info = &builder.casm_program().debug_info.sierra_statement_info;
+    let mut profiling_info = ProfilingInfo {
+        sierra_statement_weights: Default::default(),
+        stack_trace_weights: Default::default(),
+        scoped_sierra_statement_weights: Default::default(),
+    };
+    let (Some(last_step), Some(last_statement)) = (trace.last(), statement_info.last()) else {
+        return profiling_info;
+    };
+    // The program comes right after the header, which ends with the `ret` of the run.
+    let real_pc_0 = last_step.pc + 1;
+
+    // The callers of the current function with the weight each had when it called, limited to
+    // `max_stack_trace_depth`, while `depth` is the real depth of the stack.
+    let mut function_stack: Vec<(usize, usize)> = vec![];
+    let mut depth = 0;
+    let mut weight = 0;
+    for step in trace {
+        // Skips the header, and the footer after the program.
+        let Some(pc) = step
+            .pc
+            .checked_sub(real_pc_0)
+            .filter(|pc| *pc < last_statement.end_offset)
+        else {
+            continue;
+        };
+        weight += 1;
+        let idx = StatementIdx(statement_info.partition_point(|info| info.start_offset <= pc) - 1);
+        let function_idx = user_function_idx_by_sierra_statement_idx(program, idx);
+        *profiling_info
+            .sierra_statement_weights
+            .entry(idx)
+            .or_insert(0) += 1;
+        if config.collect_scoped_sierra_statement_weights {
+            // Recursive calls are collapsed into a single frame.
+            let mut stack: Vec<usize> = function_stack.iter().map(|(idx, _)| *idx).collect();
+            stack.push(function_idx);
+            stack.dedup();
+            *profiling_info
+                .scoped_sierra_statement_weights
+                .entry((stack, idx))
+                .or_insert(0) += 1;
+        }
+        match &program.statements[idx.0] {
+            Statement::Invocation(invocation) => {
+                if let Ok(CoreConcreteLibfunc::FunctionCall(_)) =
+                    builder.registry().get_libfunc(&invocation.libfunc_id)
+                {
+                    if depth < config.max_stack_trace_depth {
+                        function_stack.push((function_idx, weight));
+                        weight = 0;
+                    }
+                    depth += 1;
+                }
+            }
+            Statement::Return(_) => {
+                if depth <= config.max_stack_trace_depth {
+                    let mut stack: Vec<usize> =
+                        function_stack.iter().map(|(idx, _)| *idx).collect();
+                    stack.push(function_idx);
+                    *profiling_info.stack_trace_weights.entry(stack).or_insert(0) += weight;
+                    // The entrypoint's own return ends the program.
+                    let Some((_, caller_weight)) = function_stack.pop() else {
+                        break;
+                    };
+                    weight += caller_weight;
+                }
+                depth -= 1;
+            }
+        }
+    }
+    profiling_info
+}
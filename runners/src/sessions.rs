@@ -0,0 +1,567 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, LazyLock, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use cairo_lang_compiler::db::RootDatabase;
+use cairo_lang_defs::db::DefsGroup;
+use cairo_lang_defs::ids::{ModuleId, NamedLanguageElementId};
+use cairo_lang_runner::StarknetState;
+use cairo_lang_starknet_classes::keccak::starknet_keccak;
+use cairo_runner_types::{RunOutcome, RunStatus, Session, SessionContract};
+use serde_json::Value;
+use starknet_types_core::felt::Felt as Felt252;
+
+use crate::args::parse_felt;
+use crate::database::{DatabaseKind, PooledDatabase};
+use crate::error::RunnerError;
+use crate::main_runner::{
+    CairoProject, CompiledProgram, RunConfig, compile_program, max_available_gas, run_program,
+    setup_input_files_project,
+};
+
+/// The name of the module added to the code of a session, which the code can't use itself.
+const SESSION_MODULE_NAME: &str = "__session";
+
+/// The module added to the code of a session, whose functions make the syscalls of its requests.
+const SESSION_MODULE: &str = r#"
+use starknet::SyscallResultTrait;
+use starknet::syscalls::{call_contract_syscall, deploy_syscall, storage_read_syscall};
+
+pub fn deploy(class_hash: felt252, salt: felt252, calldata: Span<felt252>) -> felt252 {
+    let (address, _) = deploy_syscall(class_hash.try_into().unwrap(), salt, calldata, false)
+        .unwrap_syscall();
+    address.into()
+}
+
+pub fn call(address: felt252, selector: felt252, calldata: Span<felt252>) -> Span<felt252> {
+    call_contract_syscall(address.try_into().unwrap(), selector, calldata).unwrap_syscall()
+}
+
+pub fn storage(address: felt252, key: felt252) -> felt252 {
+    starknet::testing::set_contract_address(address.try_into().unwrap());
+    storage_read_syscall(0, key.try_into().unwrap()).unwrap_syscall()
+}
+"#;
+
+/// The default lifetime of an idle session, see [`session_ttl`].
+pub const DEFAULT_SESSION_TTL_SECS: u64 = 3600;
+
+/// How long a session is kept without requests, from the `SESSION_TTL_SECS` environment variable
+/// or [`DEFAULT_SESSION_TTL_SECS`].
+pub fn session_ttl() -> Duration {
+    let ttl_secs = std::env::var("SESSION_TTL_SECS")
+        .ok()
+        .and_then(|ttl| ttl.parse().ok())
+        .unwrap_or(DEFAULT_SESSION_TTL_SECS);
+    Duration::from_secs(ttl_secs)
+}
+
+/// The default cap on the live sessions, see [`max_sessions`].
+pub const DEFAULT_MAX_SESSIONS: usize = 100;
+
+/// The number of sessions a server keeps, from the `MAX_SESSIONS` environment variable or
+/// [`DEFAULT_MAX_SESSIONS`]. Creating a session beyond it drops the one closest to expiring.
+pub fn max_sessions() -> usize {
+    std::env::var("MAX_SESSIONS")
+        .ok()
+        .and_then(|max| max.parse().ok())
+        .unwrap_or(DEFAULT_MAX_SESSIONS)
+}
+
+/// The state of a session, locked by its requests.
+struct SessionState {
+    program: Arc<CompiledProgram>,
+    declared: HashSet<Felt252>,
+    /// The addresses of the deployed contracts, with their class hash, in deployment order.
+    deployed: Vec<(Felt252, Felt252)>,
+    starknet_state: StarknetState,
+}
+
+struct SessionEntry {
+    state: Arc<Mutex<SessionState>>,
+    expires_at: Instant,
+}
+
+static SESSIONS: LazyLock<Mutex<HashMap<String, SessionEntry>>> = LazyLock::new(Default::default);
+
+/// The live sessions. The map is consistent even if a request panicked while holding it, as each
+/// of its updates is a single insertion or removal.
+fn sessions() -> MutexGuard<'static, HashMap<String, SessionEntry>> {
+    SESSIONS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Whether a module of the project, at any depth, is named [`SESSION_MODULE_NAME`].
+fn declares_session_module(project: &CairoProject) -> Result<bool, RunnerError> {
+    let mut pooled_db = PooledDatabase::checkout(DatabaseKind::Program)?;
+    let db: &mut RootDatabase = &mut pooled_db;
+    let crate_id = setup_input_files_project(db, project)?;
+    Ok(db
+        .crate_modules(crate_id)
+        .iter()
+        .any(|module| match module {
+            ModuleId::Submodule(id) => id.name(db) == SESSION_MODULE_NAME,
+            ModuleId::CrateRoot(_) => false,
+        }))
+}
+
+/// Compiles a project into a new session with an empty Starknet state.
+pub fn create_session(project: impl Into<CairoProject>) -> Result<Session, RunnerError> {
+    let mut project = project.into();
+    let module_file = format!("{SESSION_MODULE_NAME}.cairo");
+    if project.files.contains_key(&module_file) || declares_session_module(&project)? {
+        return Err(RunnerError::RunnerSetup(format!(
+            "The module `{SESSION_MODULE_NAME}` is reserved for the session."
+        )));
+    }
+    if let Some(lib) = project.files.get_mut("lib.cairo") {
+        lib.push_str(&format!("\nmod {SESSION_MODULE_NAME};\n"));
+    }
+    project.files.insert(module_file, SESSION_MODULE.into());
+    let state = SessionState {
+        program: compile_program(&project)?,
+        declared: HashSet::new(),
+        deployed: vec![],
+        starknet_state: StarknetState::default(),
+    };
+    // 128 random bits from the OS, which are unique and can't be guessed.
+    let mut bytes = [0u8; 16];
+    getrandom::fill(&mut bytes)
+        .map_err(|e| RunnerError::Internal(format!("Failed to generate a session id: {e}")))?;
+    let id: String = bytes.iter().map(|byte| format!("{byte:02x}")).collect();
+    let session = session_info(&id, &state);
+
+    let mut sessions = sessions();
+    let now = Instant::now();
+    sessions.retain(|_, entry| entry.expires_at > now);
+    while !sessions.is_empty() && sessions.len() >= max_sessions() {
+        let oldest = sessions
+            .iter()
+            .min_by_key(|(_, entry)| entry.expires_at)
+            .map(|(id, _)| id.clone())
+            .unwrap();
+        sessions.remove(&oldest);
+    }
+    sessions.insert(
+        id,
+        SessionEntry {
+            state: Arc::new(Mutex::new(state)),
+            expires_at: now + session_ttl(),
+        },
+    );
+    Ok(session)
+}
+
+/// Drops a session.
+pub fn end_session(id: &str) -> Result<(), RunnerError> {
+    match sessions().remove(id) {
+        Some(_) => Ok(()),
+        None => Err(session_not_found(id)),
+    }
+}
+
+/// The contracts of a session, and where they are deployed.
+pub fn get_session(id: &str) -> Result<Session, RunnerError> {
+    let state = live_session(id)?;
+    let state = lock_session(id, &state)?;
+    Ok(session_info(id, &state))
+}
+
+/// Declares the class of `contract`, a module path or a class hash.
+pub fn declare(id: &str, contract: &str) -> Result<Session, RunnerError> {
+    let state = live_session(id)?;
+    let mut state = lock_session(id, &state)?;
+    let class_hash = find_class(&state.program, contract)?;
+    state.declared.insert(class_hash);
+    Ok(session_info(id, &state))
+}
+
+/// Deploys a declared class with its constructor's `calldata`. The outcome's `value` is the address
+/// of the contract.
+pub fn deploy(
+    id: &str,
+    contract: &str,
+    calldata: Vec<Value>,
+    salt: Option<&str>,
+    max_steps: Option<usize>,
+) -> Result<(RunOutcome, Session), RunnerError> {
+    let state = live_session(id)?;
+    let mut state = lock_session(id, &state)?;
+    let class_hash = find_class(&state.program, contract)?;
+    if !state.declared.contains(&class_hash) {
+        return Err(RunnerError::RunnerSetup(format!(
+            "The class of `{contract}` is not declared."
+        )));
+    }
+    let salt = match salt {
+        Some(salt) => Value::String(salt.into()),
+        None => Value::from(state.deployed.len()),
+    };
+    let args = vec![felt_value(class_hash), salt, Value::Array(calldata)];
+    let state = &mut *state;
+    let outcome = run_session_function(
+        &state.program,
+        "deploy",
+        args,
+        max_steps,
+        &mut state.starknet_state,
+    )?;
+    if outcome.status == RunStatus::Success {
+        let address = Felt252::from_dec_str(&outcome.values[0])
+            .map_err(|e| RunnerError::Internal(format!("Invalid deployed address: {e}")))?;
+        state.deployed.push((address, class_hash));
+    }
+    Ok((outcome, session_info(id, state)))
+}
+
+/// Calls the external `entrypoint` of a deployed contract, keeping the changes to the state if it
+/// succeeds. The outcome's `value` is the data it returned.
+pub fn invoke(
+    id: &str,
+    contract_address: &str,
+    entrypoint: &str,
+    calldata: Vec<Value>,
+    max_steps: Option<usize>,
+) -> Result<(RunOutcome, Session), RunnerError> {
+    call_entrypoint(id, contract_address, entrypoint, calldata, max_steps, true)
+}
+
+/// Calls the external `entrypoint` of a deployed contract like [`invoke`], discarding the changes
+/// to the state.
+pub fn call(
+    id: &str,
+    contract_address: &str,
+    entrypoint: &str,
+    calldata: Vec<Value>,
+    max_steps: Option<usize>,
+) -> Result<(RunOutcome, Session), RunnerError> {
+    call_entrypoint(id, contract_address, entrypoint, calldata, max_steps, false)
+}
+
+/// Reads the storage of a deployed contract at `key`, a felt or the name of a storage variable.
+/// The outcome's `value` is the stored felt.
+pub fn read_storage(
+    id: &str,
+    contract_address: &str,
+    key: &str,
+    max_steps: Option<usize>,
+) -> Result<(RunOutcome, Session), RunnerError> {
+    let state = live_session(id)?;
+    let state = lock_session(id, &state)?;
+    let (address, _) = find_deployed(&state, contract_address)?;
+    let key = parse_felt(&Value::String(key.into()))
+        .unwrap_or_else(|_| starknet_keccak(key.as_bytes()).into());
+    let outcome = run_session_function(
+        &state.program,
+        "storage",
+        vec![felt_value(address), felt_value(key)],
+        max_steps,
+        &mut state.starknet_state.clone(),
+    )?;
+    Ok((outcome, session_info(id, &state)))
+}
+
+fn call_entrypoint(
+    id: &str,
+    contract_address: &str,
+    entrypoint: &str,
+    calldata: Vec<Value>,
+    max_steps: Option<usize>,
+    keep_changes: bool,
+) -> Result<(RunOutcome, Session), RunnerError> {
+    let state = live_session(id)?;
+    let mut state = lock_session(id, &state)?;
+    let (address, class_hash) = find_deployed(&state, contract_address)?;
+    let selector: Felt252 = starknet_keccak(entrypoint.as_bytes()).into();
+    if !state.program.contracts_info[&class_hash]
+        .externals
+        .contains_key(&selector)
+    {
+        return Err(RunnerError::EntrypointNotFound(format!(
+            "The contract at {address:#x} has no external function `{entrypoint}`."
+        )));
+    }
+    let args = vec![
+        felt_value(address),
+        felt_value(selector),
+        Value::Array(calldata),
+    ];
+    let state = &mut *state;
+    let mut discarded_state;
+    let starknet_state = if keep_changes {
+        &mut state.starknet_state
+    } else {
+        discarded_state = state.starknet_state.clone();
+        &mut discarded_state
+    };
+    let outcome = run_session_function(&state.program, "call", args, max_steps, starknet_state)?;
+    Ok((outcome, session_info(id, state)))
+}
+
+/// Runs a function of [`SESSION_MODULE`] from `starknet_state`, with the gas a request can have.
+fn run_session_function(
+    program: &CompiledProgram,
+    function: &str,
+    args: Vec<Value>,
+    max_steps: Option<usize>,
+    starknet_state: &mut StarknetState,
+) -> Result<RunOutcome, RunnerError> {
+    let config = RunConfig {
+        args,
+        entrypoint: Some(format!("__session::{function}")),
+        // The syscalls charge gas, so the runs are always metered.
+        available_gas: Some(max_available_gas()),
+        max_steps,
+        ..Default::default()
+    };
+    run_program(program, None, &config, starknet_state)
+}
+
+/// The state of a live session, whose expiry is postponed by the request.
+fn live_session(id: &str) -> Result<Arc<Mutex<SessionState>>, RunnerError> {
+    let mut sessions = sessions();
+    let now = Instant::now();
+    match sessions.get_mut(id) {
+        Some(entry) if entry.expires_at > now => {
+            entry.expires_at = now + session_ttl();
+            Ok(entry.state.clone())
+        }
+        Some(_) => {
+            sessions.remove(id);
+            Err(session_not_found(id))
+        }
+        None => Err(session_not_found(id)),
+    }
+}
+
+/// Locks the state of a session. A request that panicked may have left the state half updated, so
+/// the session is dropped instead.
+fn lock_session<'a>(
+    id: &str,
+    state: &'a Mutex<SessionState>,
+) -> Result<MutexGuard<'a, SessionState>, RunnerError> {
+    state.lock().map_err(|_| {
+        sessions().remove(id);
+        RunnerError::Internal(format!(
+            "Session `{id}` was dropped, as a request to it failed unexpectedly."
+        ))
+    })
+}
+
+fn session_not_found(id: &str) -> RunnerError {
+    RunnerError::SessionNotFound(format!("No session `{id}`, it may have expired."))
+}
+
+/// The class hash of a contract of the program, from its module path or class hash.
+fn find_class(program: &CompiledProgram, contract: &str) -> Result<Felt252, RunnerError> {
+    let class_hash = parse_felt(&Value::String(contract.into())).ok();
+    program
+        .contracts_info
+        .keys()
+        .zip(&program.contract_names)
+        .find(|(hash, name)| *name == contract || Some(**hash) == class_hash)
+        .map(|(hash, _)| *hash)
+        .ok_or_else(|| {
+            RunnerError::RunnerSetup(format!("No contract `{contract}` in the session's code."))
+        })
+}
+
+/// The address and class hash of a contract the session deployed.
+fn find_deployed(
+    state: &SessionState,
+    contract_address: &str,
+) -> Result<(Felt252, Felt252), RunnerError> {
+    let address = parse_felt(&Value::String(contract_address.into()))
+        .map_err(|e| RunnerError::RunnerSetup(format!("Invalid contract address: {e}")))?;
+    state
+        .deployed
+        .iter()
+        .find(|(deployed, _)| *deployed == address)
+        .copied()
+        .ok_or_else(|| {
+            RunnerError::RunnerSetup(format!("No contract is deployed at {address:#x}."))
+        })
+}
+
+fn felt_value(felt: Felt252) -> Value {
+    Value::String(format!("{felt:#x}"))
+}
+
+fn session_info(id: &str, state: &SessionState) -> Session {
+    Session {
+        id: id.into(),
+        ttl: session_ttl().as_secs(),
+        contracts: state
+            .program
+            .contracts_info
+            .keys()
+            .zip(&state.program.contract_names)
+            .map(|(class_hash, name)| SessionContract {
+                name: name.clone(),
+                class_hash: format!("{class_hash:#x}"),
+                declared: state.declared.contains(class_hash),
+                addresses: state
+                    .deployed
+                    .iter()
+                    .filter(|(_, deployed)| deployed == class_hash)
+                    .map(|(address, _)| format!("{address:#x}"))
+                    .collect(),
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod sessions_tests {
+    use super::*;
+
+    const COUNTER: &str = r#"
+        #[starknet::interface]
+        trait ICounter<T> {
+            fn increase(ref self: T, amount: u32);
+            fn get(self: @T) -> u32;
+        }
+
+        #[starknet::contract]
+        mod counter {
+            use starknet::storage::{StoragePointerReadAccess, StoragePointerWriteAccess};
+
+            #[storage]
+            struct Storage {
+                value: u32,
+            }
+
+            #[constructor]
+            fn constructor(ref self: ContractState, initial: u32) {
+                self.value.write(initial);
+            }
+
+            #[abi(embed_v0)]
+            impl CounterImpl of super::ICounter<ContractState> {
+                fn increase(ref self: ContractState, amount: u32) {
+                    assert(amount != 0, 'zero amount');
+                    self.value.write(self.value.read() + amount);
+                }
+
+                fn get(self: @ContractState) -> u32 {
+                    self.value.read()
+                }
+            }
+        }
+    "#;
+
+    #[test]
+    fn test_session() {
+        let session = create_session(COUNTER.to_string()).unwrap();
+        let id = session.id.as_str();
+        assert_eq!(session.contracts.len(), 1);
+        assert_eq!(session.contracts[0].name, "lib::counter");
+        assert!(!session.contracts[0].declared);
+
+        let session = declare(id, "lib::counter").unwrap();
+        assert!(session.contracts[0].declared);
+
+        let (outcome, session) = deploy(id, "lib::counter", vec![3.into()], None, None).unwrap();
+        assert_eq!(outcome.status, RunStatus::Success);
+        let address = session.contracts[0].addresses[0].clone();
+        assert_eq!(
+            Felt252::from_dec_str(&outcome.values[0]).unwrap(),
+            Felt252::from_hex(&address).unwrap()
+        );
+
+        let (outcome, _) = invoke(id, &address, "increase", vec![2.into()], None).unwrap();
+        assert_eq!(outcome.status, RunStatus::Success);
+        let (outcome, _) = call(id, &address, "get", vec![], None).unwrap();
+        assert_eq!(outcome.value, Some(Value::from(vec!["5"])));
+
+        // A call doesn't keep its changes, nor does a panicking invoke.
+        let (outcome, _) = call(id, &address, "increase", vec![4.into()], None).unwrap();
+        assert_eq!(outcome.status, RunStatus::Success);
+        let (outcome, _) = invoke(id, &address, "increase", vec![0.into()], None).unwrap();
+        assert_eq!(outcome.status, RunStatus::Panic);
+        let (outcome, _) = read_storage(id, &address, "value", None).unwrap();
+        assert_eq!(outcome.values, vec!["5"]);
+
+        end_session(id).unwrap();
+        assert!(matches!(
+            get_session(id),
+            Err(RunnerError::SessionNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_session_errors() {
+        let session = create_session(COUNTER.to_string()).unwrap();
+        let id = session.id.as_str();
+        let class_hash = session.contracts[0].class_hash.clone();
+        assert_eq!(id.len(), 32);
+
+        assert!(matches!(
+            deploy(id, &class_hash, vec![0.into()], None, None),
+            Err(RunnerError::RunnerSetup(_))
+        ));
+        assert!(matches!(
+            declare(id, "lib::missing"),
+            Err(RunnerError::RunnerSetup(_))
+        ));
+
+        declare(id, &class_hash).unwrap();
+        let (_, session) = deploy(id, &class_hash, vec![0.into()], None, None).unwrap();
+        let address = &session.contracts[0].addresses[0];
+        assert!(matches!(
+            call(id, address, "decrease", vec![], None),
+            Err(RunnerError::EntrypointNotFound(_))
+        ));
+        assert!(matches!(
+            call(id, "0x1234", "get", vec![], None),
+            Err(RunnerError::RunnerSetup(_))
+        ));
+        assert!(matches!(
+            declare("missing", "lib::counter"),
+            Err(RunnerError::SessionNotFound(_))
+        ));
+
+        // A session whose state was locked by a panicking request is dropped.
+        let state = live_session(id).unwrap();
+        let _ = std::thread::spawn(move || {
+            let _state = state.lock().unwrap();
+            panic!("The request failed.");
+        })
+        .join();
+        assert!(matches!(get_session(id), Err(RunnerError::Internal(_))));
+        assert!(matches!(
+            get_session(id),
+            Err(RunnerError::SessionNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_session_module_reserved() {
+        let project = |files: &[(&str, &str)]| {
+            let mut project = CairoProject::from(COUNTER.to_string());
+            for (path, code) in files {
+                project
+                    .files
+                    .entry(path.to_string())
+                    .or_default()
+                    .push_str(code);
+            }
+            project
+        };
+        let reserved = [
+            &[("lib.cairo", "mod __session;")][..],
+            &[("lib.cairo", "mod __session {\n    fn f() {}\n}")],
+            &[("lib.cairo", "mod foo;"), ("foo.cairo", "mod __session {}")],
+            &[("__session.cairo", "fn f() {}")],
+        ];
+        for files in reserved {
+            assert!(matches!(
+                create_session(project(files)),
+                Err(RunnerError::RunnerSetup(_))
+            ));
+        }
+
+        // Only declarations count, not the name in comments or strings.
+        let code = "// mod __session;\nfn name() -> ByteArray { \"mod __session\" }";
+        create_session(project(&[("lib.cairo", code)])).unwrap();
+    }
+}
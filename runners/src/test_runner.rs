@@ -1,31 +1,47 @@
 use cairo_lang_compiler::{db::RootDatabase, diagnostics::DiagnosticsReporter};
-use cairo_lang_filesystem::cfg::{Cfg, CfgSet};
-use cairo_lang_runner::{RunResultValue, SierraCasmRunner};
-use cairo_lang_sierra::program::Program;
+use cairo_lang_runnable_utils::builder::RunnableBuilder;
+use cairo_lang_runner::{
+    casm_run::format_for_panic, profiling::ProfilingInfo, RunResultValue, SierraCasmRunner,
+    StarknetExecutionResources,
+};
+use cairo_lang_sierra::program::StatementIdx;
 use cairo_lang_sierra_to_casm::metadata::MetadataComputationConfig;
 use cairo_lang_test_plugin::{
     compile_test_prepared_db,
     test_config::{PanicExpectation, TestExpectation},
-    test_plugin_suite, TestConfig, TestsCompilationConfig,
+    TestCompilation, TestCompilationMetadata, TestConfig, TestsCompilationConfig,
 };
-use cairo_lang_test_runner::{RunProfilerConfig, TestRunConfig};
+use cairo_lang_test_runner::{filter_test_cases, RunProfilerConfig, TestRunConfig};
 
-use std::sync::Mutex;
+use std::sync::{Arc, LazyLock};
 
-use anyhow::{bail, Context, Result};
 use cairo_lang_runner::ProfilingInfoCollectionConfig;
-use cairo_lang_starknet::{contract::ContractInfo, starknet_plugin_suite};
-use cairo_lang_utils::ordered_hash_map::OrderedHashMap;
-// use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
 use starknet_types_core::felt::Felt as Felt252;
 
-use crate::main_runner::setup_input_string_project;
+use cairo_runner_types::{
+    BacktraceFrame, CairoRunRequest, Diagnostic, MemoryDump, MemoryRange, ProfilerKind,
+    ResourceUsage, TestCaseResult, TestCaseStatus,
+};
+
+use crate::backtrace::BacktraceMap;
+use crate::cache::{cache_capacity, cache_key, CacheStats, CompilationCache};
+use crate::database::{DatabaseKind, PooledDatabase};
+use crate::decode::decode_panic_data;
+use crate::diagnostics::{collect_diagnostics, CompilationError};
+use crate::error::RunnerError;
+use crate::execution::{run_function, FunctionRun, RunOptions};
+use crate::main_runner::{max_steps, setup_input_files_project, CairoProject};
+use crate::profiling::Profiler;
+use crate::trace::memory_dump;
 
 #[derive(Debug)]
 pub struct TestsSummary {
     passed: Vec<String>,
     failed: Vec<String>,
     failed_run_results: Vec<RunResultValue>,
+    results: Vec<TestCaseResult>,
+    diagnostics: Vec<Diagnostic>,
     notes: String,
 }
 
@@ -39,14 +55,28 @@ impl TestsSummary {
     pub fn failed_run_results(&self) -> &[RunResultValue] {
         &self.failed_run_results
     }
+    /// The per-test results, in the order the tests ran.
+    pub fn results(&self) -> &[TestCaseResult] {
+        &self.results
+    }
+    /// The compiler warnings of the tested code.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
     pub fn notes(&self) -> &str {
         &self.notes
     }
+    /// What the tests printed, in the order they ran.
+    pub fn stdout(&self) -> String {
+        self.results.iter().map(|r| r.stdout.as_str()).collect()
+    }
 }
 
 enum TestStatus {
-    Success,
+    /// The test passed, carrying the panic data if it was expected to panic.
+    Success(Option<Vec<Felt252>>),
     Fail(RunResultValue),
+    StepLimitExceeded,
 }
 
 /// The result of a ran test.
@@ -55,26 +85,103 @@ struct TestResult {
     status: TestStatus,
     /// The gas usage of the run if relevant.
     gas_usage: Option<i64>,
-    // /// The used resources of the run.
-    // used_resources: StarknetExecutionResources,
-    // /// The profiling info of the run, if requested.
-    // profiling_info: Option<ProfilingInfo>,
+    /// The exact panic data the test expects, if any.
+    expected_panic_data: Option<Vec<Felt252>>,
+    /// What the test printed.
+    stdout: String,
+    /// The used resources of the run, `None` if it hit the step limit.
+    used_resources: Option<StarknetExecutionResources>,
+    /// The profiling info of the run, if requested.
+    profiling_info: Option<ProfilingInfo>,
+    /// Where the run panicked, if it did.
+    panic_statements: Vec<StatementIdx>,
+    /// The requested cells of the memory.
+    memory: Option<MemoryDump>,
 }
 
-pub fn run_cairo_tests(code: String) -> anyhow::Result<TestsSummary> {
-    let cfg = CfgSet::from_iter([Cfg::name("test"), Cfg::kv("target", "test")]);
-    let mut db_builder = RootDatabase::builder();
-    db_builder.detect_corelib();
-    db_builder.with_cfg(cfg);
-    db_builder.with_default_plugin_suite(test_plugin_suite());
-    db_builder.with_default_plugin_suite(starknet_plugin_suite());
-    let db = &mut db_builder.build()?;
+/// Options for running the tests of a project.
+#[derive(Debug, Clone, Default)]
+pub struct TestsConfig {
+    /// Only run the tests whose full path contains this string, e.g. `test_fib`.
+    pub filter: String,
+    /// Also run the `#[ignore]`d tests.
+    pub include_ignored: bool,
+    /// Only run the `#[ignore]`d tests.
+    pub ignored_only: bool,
+    /// Compute precise gas costs and report the gas usage of each test.
+    pub gas_enabled: bool,
+    /// Profile each test.
+    pub profile: Option<ProfilerKind>,
+    /// The number of tests run in parallel. Defaults to the number of CPUs.
+    pub jobs: Option<usize>,
+    /// Return these cells of the memory of each test, see [`TestCaseResult::memory`].
+    pub memory: Option<MemoryRange>,
+    /// The number of CASM steps after which a test is stopped, see
+    /// [`crate::main_runner::RunConfig::max_steps`].
+    pub max_steps: Option<usize>,
+}
+
+impl TestsConfig {
+    /// The options of a test request, within the limits of the server.
+    pub fn from_request(request: &CairoRunRequest) -> Self {
+        Self {
+            filter: request.filter.clone().unwrap_or_default(),
+            include_ignored: request.include_ignored.unwrap_or_default(),
+            ignored_only: request.ignored_only.unwrap_or_default(),
+            gas_enabled: request.gas_enabled.unwrap_or_default(),
+            profile: request.profile,
+            jobs: Some(
+                request
+                    .jobs
+                    .map_or(max_test_jobs(), |jobs| jobs.clamp(1, max_test_jobs())),
+            ),
+            memory: request.memory,
+            max_steps: Some(max_steps()),
+        }
+    }
+}
 
-    let crate_id = setup_input_string_project(db, code)?;
+/// The default cap on the `jobs` of a request, see [`max_test_jobs`].
+pub const DEFAULT_MAX_TEST_JOBS: usize = 4;
 
+/// The largest number of tests a server runs in parallel for a request, from the `MAX_TEST_JOBS`
+/// environment variable or [`DEFAULT_MAX_TEST_JOBS`], and at least 1.
+pub fn max_test_jobs() -> usize {
+    std::env::var("MAX_TEST_JOBS")
+        .ok()
+        .and_then(|max| max.parse().ok())
+        .unwrap_or(DEFAULT_MAX_TEST_JOBS)
+        .max(1)
+}
+
+/// A project compiled for testing, which is shared by the test runs of the same sources.
+pub struct CompiledTests {
+    pub compiled: TestCompilation,
+    /// Compiler warnings.
+    pub diagnostics: Vec<Diagnostic>,
+    /// The source locations of the statements of panic backtraces.
+    pub backtrace_map: BacktraceMap,
+}
+
+static TESTS: LazyLock<CompilationCache<CompiledTests>> =
+    LazyLock::new(|| CompilationCache::new(cache_capacity()));
+
+/// The hits and misses of the compilations of [`run_cairo_tests`].
+pub fn tests_cache_stats() -> CacheStats {
+    TESTS.stats()
+}
+
+/// Compiles the tests of a project in `db`.
+fn compile_tests(
+    db: &mut RootDatabase,
+    project: &CairoProject,
+) -> Result<CompiledTests, RunnerError> {
+    let crate_id = setup_input_files_project(db, project)?;
+
+    let collected_diagnostics = collect_diagnostics(db, &[crate_id]);
     let mut diagnostics = String::new();
 
-    let compiled = match compile_test_prepared_db(
+    match compile_test_prepared_db(
         db,
         TestsCompilationConfig {
             starknet: true,
@@ -87,107 +194,247 @@ pub fn run_cairo_tests(code: String) -> anyhow::Result<TestsSummary> {
         vec![crate_id],
         DiagnosticsReporter::callback(|diagnostic| {
             diagnostics += format!("{}\n", diagnostic).as_str()
-        }),
+        })
+        .allow_warnings(),
     ) {
-        Ok(compiled) => compiled,
-        Err(err) => {
-            bail!("{err}\n\n{diagnostics}")
+        Ok(compiled) => Ok(CompiledTests {
+            backtrace_map: compiled
+                .metadata
+                .statements_locations
+                .as_ref()
+                .map(|locations| BacktraceMap::new(db, &compiled.sierra_program.program, locations))
+                .unwrap_or_default(),
+            compiled,
+            diagnostics: collected_diagnostics,
+        }),
+        Err(err) => Err(CompilationError {
+            message: format!("{err}\n\n{diagnostics}"),
+            diagnostics: collected_diagnostics,
         }
+        .into()),
+    }
+}
+
+pub fn run_cairo_tests(
+    project: impl Into<CairoProject>,
+    tests_config: &TestsConfig,
+) -> Result<TestsSummary, RunnerError> {
+    let project = project.into();
+    // The profiler resolves names in the database of the compilation, which is not cached.
+    let mut profiled_db: Option<PooledDatabase> = None;
+    let tests = match tests_config.profile {
+        Some(_) => Arc::new(compile_tests(
+            profiled_db.insert(PooledDatabase::checkout(DatabaseKind::Tests)?),
+            &project,
+        )?),
+        None => TESTS.get_or_try_insert(cache_key("tests", &project), || {
+            let mut db = PooledDatabase::checkout(DatabaseKind::Tests)?;
+            compile_tests(&mut db, &project)
+        })?,
     };
 
     let config = TestRunConfig {
-        filter: "".into(),
-        include_ignored: false,
-        ignored: false,
-        run_profiler: RunProfilerConfig::None,
-        gas_enabled: false,
+        filter: tests_config.filter.clone(),
+        include_ignored: tests_config.include_ignored,
+        ignored: tests_config.ignored_only,
+        run_profiler: match tests_config.profile {
+            None => RunProfilerConfig::None,
+            Some(ProfilerKind::Sierra) => RunProfilerConfig::Sierra,
+            Some(ProfilerKind::Cairo) => RunProfilerConfig::Cairo,
+        },
+        gas_enabled: tests_config.gas_enabled,
         print_resource_usage: false,
     };
+    let (compiled, _) = filter_test_cases(
+        tests.compiled.clone(),
+        config.include_ignored,
+        config.ignored,
+        &config.filter,
+    );
 
-    Ok(run_tests(
-        compiled.metadata.named_tests,
-        compiled.sierra_program.program,
-        compiled.metadata.contracts_info,
+    let profiler = tests_config
+        .profile
+        .zip(profiled_db.as_deref())
+        .map(|(kind, db)| {
+            Profiler::new(
+                kind,
+                db,
+                compiled.sierra_program.program.clone(),
+                compiled
+                    .metadata
+                    .statements_locations
+                    .as_ref()
+                    .map(|locations| locations.get_statements_functions_map_for_tests(db))
+                    .unwrap_or_default(),
+            )
+        });
+    let mut summary = run_tests(
+        profiler,
+        compiled,
+        &tests.backtrace_map,
         &config,
-    )?)
+        tests_config,
+    )?;
+    summary.diagnostics = tests.diagnostics.clone();
+    Ok(summary)
 }
 
 /// Updates the test summary with the given test result.
 fn update_summary(
-    wrapped_summary: &Mutex<std::prelude::v1::Result<TestsSummary, anyhow::Error>>,
-    test_result: std::prelude::v1::Result<(String, Option<TestResult>), anyhow::Error>,
+    summary: &mut TestsSummary,
+    (name, opt_result): (String, Option<TestResult>),
+    profiler: &Option<Profiler<'_>>,
+    backtrace: impl Fn(&[StatementIdx]) -> Vec<BacktraceFrame>,
 ) {
-    let mut wrapped_summary = wrapped_summary.lock().unwrap();
-    if wrapped_summary.is_err() {
-        return;
-    }
-    let (name, opt_result) = match test_result {
-        Ok((name, opt_result)) => (name, opt_result),
-        Err(err) => {
-            *wrapped_summary = Err(err);
-            return;
-        }
-    };
-    let summary = wrapped_summary.as_mut().unwrap();
     let mut empty_tests: Vec<String> = vec![];
+    let mut record = TestCaseResult {
+        name: name.clone(),
+        status: TestCaseStatus::Ignored,
+        gas_usage: None,
+        panic_data: None,
+        backtrace: None,
+        expected_panic_data: None,
+        failure_reason: None,
+        stdout: String::new(),
+        resources: None,
+        profile: None,
+        memory: None,
+    };
     let (res_type, status_str, gas_usage) = if let Some(result) = opt_result {
+        record.expected_panic_data = result.expected_panic_data.as_deref().map(decode_panic_data);
+        record.stdout = result.stdout;
+        record.resources = result.used_resources.as_ref().map(resource_usage);
+        record.memory = result.memory;
+        record.profile = result
+            .profiling_info
+            .as_ref()
+            .zip(profiler.as_ref())
+            .map(|(profiling_info, profiler)| profiler.profile(profiling_info));
         let (res_type, status_str) = match result.status {
-            TestStatus::Success => (&mut summary.passed, "ok"),
+            TestStatus::Success(panic_data) => {
+                record.status = TestCaseStatus::Ok;
+                record.panic_data = panic_data.as_deref().map(decode_panic_data);
+                (&mut summary.passed, "ok")
+            }
             TestStatus::Fail(run_result) => {
+                record.status = TestCaseStatus::Fail;
+                record.failure_reason = Some(match &run_result {
+                    RunResultValue::Success(_) => {
+                        "expected panic but finished successfully.".to_string()
+                    }
+                    RunResultValue::Panic(values) => {
+                        record.panic_data = Some(decode_panic_data(values));
+                        let reason = format_for_panic(values.clone().into_iter());
+                        match &result.expected_panic_data {
+                            Some(expected) => format!(
+                                "{reason} Expected {}",
+                                format_for_panic(expected.clone().into_iter())
+                                    .trim_start_matches("Panicked with ")
+                            ),
+                            None => reason,
+                        }
+                    }
+                });
                 summary.failed_run_results.push(run_result);
                 (&mut summary.failed, "fail")
             }
+            TestStatus::StepLimitExceeded => {
+                record.status = TestCaseStatus::StepLimitExceeded;
+                record.failure_reason = Some("exceeded the step limit.".to_string());
+                (&mut summary.failed, "step limit exceeded")
+            }
         };
+        if record.panic_data.is_some() {
+            record.backtrace = Some(backtrace(&result.panic_statements));
+        }
         (res_type, status_str, result.gas_usage)
     } else {
         (&mut empty_tests, "ignored", None)
     };
+    record.gas_usage = gas_usage;
     if let Some(gas_usage) = gas_usage {
         summary.notes += &format!("\ntest {name} ... {status_str} (gas usage est.: {gas_usage})");
     } else {
         summary.notes += &format!("\ntest {name} ... {status_str}");
     }
 
+    summary.results.push(record);
     res_type.push(name);
 }
 
 /// Runs the tests and process the results for a summary.
 pub fn run_tests(
-    named_tests: Vec<(String, TestConfig)>,
-    sierra_program: Program,
-    contracts_info: OrderedHashMap<Felt252, ContractInfo>,
+    profiler: Option<Profiler<'_>>,
+    compiled: TestCompilation,
+    backtrace_map: &BacktraceMap,
     config: &TestRunConfig,
-) -> Result<TestsSummary> {
-    let runner = SierraCasmRunner::new(
-        sierra_program.clone(),
-        Some(MetadataComputationConfig::default()),
+    tests_config: &TestsConfig,
+) -> Result<TestsSummary, RunnerError> {
+    let TestCompilationMetadata {
         contracts_info,
-        match config.run_profiler {
-            RunProfilerConfig::None => None,
-            RunProfilerConfig::Cairo | RunProfilerConfig::Sierra => {
-                Some(ProfilingInfoCollectionConfig::default())
+        function_set_costs,
+        named_tests,
+        ..
+    } = compiled.metadata;
+    let sierra_program = compiled.sierra_program.program;
+    // `gas_enabled` makes the costs of the test functions precise, as `cairo-test` does. A test
+    // with `#[available_gas(static)]` starts with no gas beyond its static cost, but one without
+    // the attribute gets `u32::MAX`, so it's `max_steps` that stops an infinite loop.
+    let metadata_config = || {
+        if config.gas_enabled {
+            MetadataComputationConfig {
+                function_set_costs: function_set_costs.clone(),
+                linear_gas_solver: true,
+                linear_ap_change_solver: true,
+                skip_non_linear_solver_comparisons: false,
+                compute_runtime_costs: false,
             }
-        },
+        } else {
+            MetadataComputationConfig::default()
+        }
+    };
+    let builder = RunnableBuilder::new(sierra_program.clone(), Some(metadata_config()))
+        .map_err(|e| RunnerError::RunnerSetup(format!("Failed setting up runner: {e}")))?;
+    let runner = SierraCasmRunner::new(
+        sierra_program,
+        Some(metadata_config()),
+        contracts_info,
+        None,
     )
-    .with_context(|| "Failed setting up runner.")?;
+    .map_err(|e| RunnerError::RunnerSetup(format!("Failed setting up runner: {e}")))?;
     let suffix = if named_tests.len() != 1 { "s" } else { "" };
     let notes = format!("running {} test{}", named_tests.len(), suffix);
-    let wrapped_summary = Mutex::new(Ok(TestsSummary {
+    let mut summary = TestsSummary {
         passed: vec![],
         failed: vec![],
         failed_run_results: vec![],
+        results: vec![],
+        diagnostics: vec![],
         notes,
-    }));
-
-    // Run in parallel if possible. If running with db, parallelism is impossible.
-    named_tests
-        .into_iter()
-        .map(move |(name, test)| run_single_test(test, name, &runner))
-        .for_each(|test_result| {
-            update_summary(&wrapped_summary, test_result);
+    };
+
+    // The tests run in parallel, and their results are summarized in order so the summary does
+    // not depend on the scheduling. The profiles are processed while summarizing, as the db can't
+    // be shared between threads.
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(tests_config.jobs.unwrap_or_default())
+        .build()
+        .map_err(|e| RunnerError::Internal(format!("Failed to build the test thread pool: {e}")))?;
+    let test_results: Vec<_> = pool.install(|| {
+        named_tests
+            .into_par_iter()
+            .map(|(name, test)| {
+                run_single_test(test, name, &runner, &builder, config, tests_config)
+            })
+            .collect()
+    });
+    for test_result in test_results {
+        update_summary(&mut summary, test_result?, &profiler, |statements| {
+            backtrace_map.backtrace(builder.sierra_program(), statements)
         });
+    }
 
-    wrapped_summary.into_inner().unwrap()
+    Ok(summary)
 }
 
 /// Runs a single test and returns a tuple of its name and result.
@@ -195,20 +442,71 @@ fn run_single_test(
     test: TestConfig,
     name: String,
     runner: &SierraCasmRunner,
-) -> anyhow::Result<(String, Option<TestResult>)> {
+    builder: &RunnableBuilder,
+    config: &TestRunConfig,
+    tests_config: &TestsConfig,
+) -> Result<(String, Option<TestResult>), RunnerError> {
     if test.ignored {
         return Ok((name, None));
     }
-    let func = runner.find_function(name.as_str())?;
-    let result = runner
-        .run_function_with_starknet_context(func, vec![], test.available_gas, Default::default())
-        .with_context(|| format!("Failed to run the function `{}`.", name.as_str()))?;
+    let func = runner
+        .find_function(name.as_str())
+        .map_err(|e| RunnerError::EntrypointNotFound(e.to_string()))?;
+    let FunctionRun {
+        result,
+        stdout,
+        panic_statements,
+        trace,
+        ..
+    } = run_function(
+        runner,
+        builder,
+        func,
+        vec![],
+        RunOptions {
+            available_gas: test.available_gas,
+            max_steps: tests_config.max_steps,
+            trace: tests_config
+                .memory
+                .is_some_and(|range| range.segment.is_some()),
+            profiling: match config.run_profiler {
+                RunProfilerConfig::None => None,
+                RunProfilerConfig::Cairo | RunProfilerConfig::Sierra => {
+                    Some(ProfilingInfoCollectionConfig::default())
+                }
+            },
+            ..Default::default()
+        },
+    )?;
+    let expected_panic_data = match &test.expectation {
+        TestExpectation::Panics(PanicExpectation::Exact(expected)) => Some(expected.clone()),
+        _ => None,
+    };
+    let Some(result) = result else {
+        return Ok((
+            name,
+            Some(TestResult {
+                status: TestStatus::StepLimitExceeded,
+                gas_usage: None,
+                expected_panic_data,
+                stdout,
+                used_resources: None,
+                profiling_info: None,
+                panic_statements: vec![],
+                memory: None,
+            }),
+        ));
+    };
+    let memory = tests_config.memory.map(|range| {
+        let segments = trace.as_ref().map_or(&[][..], |trace| &trace.segments);
+        memory_dump(&range, &result.memory, segments)
+    });
     Ok((
         name,
         Some(TestResult {
             status: match &result.value {
                 RunResultValue::Success(_) => match test.expectation {
-                    TestExpectation::Success => TestStatus::Success,
+                    TestExpectation::Success => TestStatus::Success(None),
                     TestExpectation::Panics(_) => TestStatus::Fail(result.value),
                 },
                 RunResultValue::Panic(value) => match test.expectation {
@@ -217,28 +515,60 @@ fn run_single_test(
                         PanicExpectation::Exact(expected) if value != &expected => {
                             TestStatus::Fail(result.value)
                         }
-                        _ => TestStatus::Success,
+                        _ => TestStatus::Success(Some(value.clone())),
                     },
                 },
             },
-            gas_usage: result
-                .gas_counter
-                .map(|f| *f.to_bigint().to_u64_digits().1.first().unwrap_or(&0) as i64),
-            // used_resources: result.used_resources,
-            // profiling_info: result.profiling_info,
+            expected_panic_data,
+            stdout,
+            gas_usage: config
+                .gas_enabled
+                .then(|| {
+                    test.available_gas
+                        .zip(result.gas_counter)
+                        .map(|(before, after)| {
+                            before as i64 - u64::try_from(after).unwrap_or_default() as i64
+                        })
+                        .or_else(|| runner.initial_required_gas(func).map(|gas| gas as i64))
+                })
+                .flatten(),
+            used_resources: Some(result.used_resources),
+            profiling_info: result.profiling_info,
+            panic_statements,
+            memory,
         }),
     ))
 }
 
+/// Converts the resources used by a test, leaving out the unused builtins.
+fn resource_usage(used_resources: &StarknetExecutionResources) -> ResourceUsage {
+    let basic_resources = used_resources.basic_resources.filter_unused_builtins();
+    ResourceUsage {
+        steps: basic_resources.n_steps,
+        memory_holes: basic_resources.n_memory_holes,
+        builtins: basic_resources
+            .builtin_instance_counter
+            .into_iter()
+            .map(|(builtin, count)| (builtin.to_string(), count))
+            .collect(),
+        syscalls: used_resources
+            .syscalls
+            .iter()
+            .map(|(syscall, count)| (syscall.clone(), *count))
+            .collect(),
+    }
+}
+
 #[cfg(test)]
 mod test_runner_tests {
     use super::*;
+    use cairo_runner_types::PanicItem;
 
     #[test]
     fn fail_compilation() {
         let code = r#"fn main(){}{"#;
 
-        match run_cairo_tests(code.to_string()) {
+        match run_cairo_tests(code.to_string(), &TestsConfig::default()) {
             Ok(_) => {
                 panic!("Error compilation should fail")
             }
@@ -254,7 +584,7 @@ mod test_runner_tests {
 fn main(){// this is some Cairo code
 }"#;
 
-        match run_cairo_tests(code.to_string()) {
+        match run_cairo_tests(code.to_string(), &TestsConfig::default()) {
             Ok(output) => {
                 assert!(
                     output.failed.is_empty()
@@ -274,19 +604,40 @@ fn main(){// this is some Cairo code
         assert(false, "fail diagnostics");
     }
     "#;
-        match run_cairo_tests(code.to_string()) {
+        match run_cairo_tests(code.to_string(), &TestsConfig::default()) {
             Ok(_) => panic!("Error: test tunner should fail with diagnostics"),
             Err(e) => {
                 let e = e.to_string();
                 assert!(e.contains("Mismatched types. The type `core::felt252` cannot be created from a string literal."));
-                assert!(e
-                    .to_string()
-                    .contains("assert(false, \"fail diagnostics\");"));
+                assert!(
+                    e.to_string()
+                        .contains("assert(false, \"fail diagnostics\");")
+                );
                 assert!(e.contains("  ^^^^^^^^^^^^^^^^^")); // some sorta pointer
             }
         }
     }
 
+    #[test]
+    fn structured_diagnostics_test() {
+        let code = r#"
+    #[test]
+    fn test_failing() {
+        assert(false, "fail diagnostics");
+    }
+    "#;
+        let error = run_cairo_tests(code.to_string(), &TestsConfig::default()).unwrap_err();
+        let RunnerError::Compilation(error) = error else {
+            panic!("Expected a compilation error, got {error}");
+        };
+        assert!(error.diagnostics.iter().any(|d| {
+            d.file == "lib.cairo"
+                && d.start.0 == 3
+                && d.message
+                    .contains("cannot be created from a string literal")
+        }));
+    }
+
     #[test]
     fn test_panic() {
         let code = r#"
@@ -295,12 +646,18 @@ fn main(){// this is some Cairo code
     	assert(false, 'should fail');
     }
     "#;
-        match run_cairo_tests(code.to_string()) {
+        match run_cairo_tests(code.to_string(), &TestsConfig::default()) {
             Ok(output) => {
                 assert!(output.passed.is_empty());
                 assert!(output.failed.len() == 1);
                 assert!(output.failed_run_results.len() == 1);
                 assert!(output.notes.contains("test lib::test_failing ... fail"));
+                let backtrace = output.results()[0].backtrace.as_ref().unwrap();
+                let frame = backtrace
+                    .iter()
+                    .find(|frame| frame.function == "lib::test_failing")
+                    .unwrap();
+                assert_eq!(frame.location.as_ref().unwrap().file, "lib.cairo");
             }
             Err(e) => panic!("Error: {}", e),
         }
@@ -314,12 +671,12 @@ fn main(){// this is some Cairo code
     	assert(true, 'should pass');
     }
     "#;
-        match run_cairo_tests(code.to_string()) {
+        match run_cairo_tests(code.to_string(), &TestsConfig::default()) {
             Ok(output) => {
-                assert!(output.passed.is_empty());
-                assert!(output.failed.len() == 1);
-                assert!(output.failed_run_results.len() == 1);
-                assert!(output.notes.contains("test lib::test_failing ... fail"));
+                assert!(output.passed.len() == 1);
+                assert!(output.failed.is_empty());
+                assert!(output.failed_run_results.is_empty());
+                assert!(output.notes.contains("test lib::test_pass ... ok"));
             }
             Err(e) => panic!("Error: {}", e),
         }
@@ -334,7 +691,7 @@ fn main(){// this is some Cairo code
     	assert(false, 'panics');
     }
     "#;
-        match run_cairo_tests(code.to_string()) {
+        match run_cairo_tests(code.to_string(), &TestsConfig::default()) {
             Ok(output) => {
                 assert!(output.passed.len() == 1);
                 assert!(output.failed.is_empty());
@@ -374,7 +731,7 @@ mod test {
         assert('Joe' == contract.get_owner(), 'Joe should be the owner.');
     }
 }"#;
-        match run_cairo_tests(code.to_string()) {
+        match run_cairo_tests(code.to_string(), &TestsConfig::default()) {
             Ok(res) => {
                 assert!(res.passed.len() == 1);
                 assert!(res.failed.is_empty());
@@ -384,4 +741,322 @@ mod test {
             Err(e) => println!("\n\nError: ```{}```\n\n", e),
         }
     }
+
+    #[test]
+    fn test_results() {
+        let code = r#"
+    #[test]
+    fn test_ok() {}
+
+    #[test]
+    fn test_fail() {
+    	assert(false, 'should fail');
+    }
+
+    #[test]
+    #[should_panic(expected: ('other',))]
+    fn test_wrong_panic() {
+    	assert(false, 'panics');
+    }
+
+    #[test]
+    #[ignore]
+    fn test_ignored() {}
+    "#;
+        let output = run_cairo_tests(code.to_string(), &TestsConfig::default()).unwrap();
+        let results = output.results();
+        assert_eq!(results.len(), 4);
+
+        assert_eq!(results[0].name, "lib::test_ok");
+        assert_eq!(results[0].status, TestCaseStatus::Ok);
+        assert!(results[0].failure_reason.is_none());
+
+        assert_eq!(results[1].name, "lib::test_fail");
+        assert_eq!(results[1].status, TestCaseStatus::Fail);
+        assert_eq!(
+            results[1].panic_data,
+            Some(vec![PanicItem::Felt {
+                value: "0x73686f756c64206661696c".into(),
+                short_string: Some("should fail".into()),
+            }])
+        );
+        assert!(
+            results[1]
+                .failure_reason
+                .as_ref()
+                .unwrap()
+                .contains("should fail")
+        );
+
+        assert_eq!(results[2].status, TestCaseStatus::Fail);
+        assert_eq!(
+            results[2].expected_panic_data,
+            Some(vec![PanicItem::Felt {
+                value: "0x6f74686572".into(),
+                short_string: Some("other".into()),
+            }])
+        );
+
+        assert_eq!(results[3].name, "lib::test_ignored");
+        assert_eq!(results[3].status, TestCaseStatus::Ignored);
+    }
+
+    #[test]
+    fn test_stdout() {
+        let code = r#"
+    #[test]
+    fn test_a() {
+        println!("from a");
+    }
+
+    #[test]
+    fn test_b() {
+        println!("from b");
+        assert(false, 'fails');
+    }
+    "#;
+        let output = run_cairo_tests(code.to_string(), &TestsConfig::default()).unwrap();
+        assert_eq!(output.results()[0].stdout, "from a\n");
+        assert_eq!(output.results()[1].stdout, "from b\n");
+        assert_eq!(output.stdout(), "from a\nfrom b\n");
+    }
+
+    #[test]
+    fn test_filter_and_ignored() {
+        let code = r#"
+    #[test]
+    fn test_fast() {}
+
+    #[test]
+    fn test_other() {}
+
+    #[test]
+    #[ignore]
+    fn test_slow() {}
+    "#;
+        let run = |config: TestsConfig| {
+            let output = run_cairo_tests(code.to_string(), &config).unwrap();
+            output
+                .results()
+                .iter()
+                .map(|r| (r.name.clone(), r.status))
+                .collect::<Vec<_>>()
+        };
+
+        let results = run(TestsConfig {
+            filter: "test_fast".into(),
+            ..Default::default()
+        });
+        assert_eq!(results, vec![("lib::test_fast".into(), TestCaseStatus::Ok)]);
+
+        let results = run(TestsConfig {
+            include_ignored: true,
+            ..Default::default()
+        });
+        assert_eq!(results.len(), 3);
+        assert!(
+            results
+                .iter()
+                .all(|(_, status)| *status == TestCaseStatus::Ok)
+        );
+
+        let results = run(TestsConfig {
+            ignored_only: true,
+            ..Default::default()
+        });
+        assert_eq!(results, vec![("lib::test_slow".into(), TestCaseStatus::Ok)]);
+    }
+
+    #[test]
+    fn test_gas_and_resources() {
+        let code = r#"
+    #[test]
+    fn test_sum() {
+        let mut i: u32 = 0;
+        let mut sum: u32 = 0;
+        while i < 10 {
+            sum += i;
+            i += 1;
+        };
+        assert(sum == 45, 'wrong sum');
+    }
+    "#;
+        let output = run_cairo_tests(code.to_string(), &TestsConfig::default()).unwrap();
+        let result = &output.results()[0];
+        assert_eq!(result.gas_usage, None);
+        let resources = result.resources.as_ref().unwrap();
+        assert!(resources.steps > 0);
+        assert!(resources.builtins.contains_key("range_check_builtin"));
+
+        let output = run_cairo_tests(
+            code.to_string(),
+            &TestsConfig {
+                gas_enabled: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let result = &output.results()[0];
+        assert_eq!(result.status, TestCaseStatus::Ok);
+        assert!(result.gas_usage.unwrap() > 0);
+    }
+
+    #[test]
+    fn test_parallel_jobs_keep_order() {
+        let code = (0..8)
+            .map(|i| format!("#[test]\nfn test_{i}() {{ assert({i} + 1 != 0, 'zero'); }}\n"))
+            .collect::<String>();
+        let names = |jobs| {
+            let config = TestsConfig {
+                jobs: Some(jobs),
+                ..Default::default()
+            };
+            let output = run_cairo_tests(code.clone(), &config).unwrap();
+            assert_eq!(output.passed().len(), 8);
+            output
+                .results()
+                .iter()
+                .map(|r| r.name.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(4), names(1));
+    }
+
+    #[test]
+    fn test_memory() {
+        let code = r#"
+    #[test]
+    fn test_sum() {
+        let sum: felt252 = 1234 + 4321;
+        assert(sum == 5555, 'wrong sum');
+    }
+    "#;
+        let output = run_cairo_tests(code.to_string(), &TestsConfig::default()).unwrap();
+        assert_eq!(output.results()[0].memory, None);
+
+        // The program segment holds the bytecode, right after the unused address 0.
+        let config = TestsConfig {
+            memory: Some(MemoryRange {
+                segment: Some(0),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let output = run_cairo_tests(code.to_string(), &config).unwrap();
+        let memory = output.results()[0].memory.as_ref().unwrap();
+        assert_eq!(memory.segment, Some(0));
+        assert_eq!(memory.address, 1);
+        assert!(!memory.cells.is_empty());
+        assert!(memory.cells.iter().all(Option::is_some));
+    }
+
+    #[test]
+    fn test_step_limit() {
+        let run = |available_gas: &str| {
+            let code = format!(
+                r#"
+    #[test]
+    #[available_gas({available_gas})]
+    fn test_loop() {{
+        loop {{}}
+    }}
+
+    #[test]
+    fn test_pass() {{}}
+    "#
+            );
+            let config = TestsConfig {
+                max_steps: Some(100_000),
+                ..Default::default()
+            };
+            run_cairo_tests(code, &config)
+        };
+
+        // A static test has no gas beyond the cost of its body, so a loop runs out of it at once.
+        let output = run("static").unwrap();
+        assert_eq!(output.results()[0].status, TestCaseStatus::Fail);
+        assert!(
+            output.results()[0]
+                .failure_reason
+                .as_ref()
+                .unwrap()
+                .contains("Out of gas")
+        );
+
+        // With more gas than it can use, it runs until the step limit, without stopping the
+        // other tests.
+        let output = run("1000000000000").unwrap();
+        let results = output.results();
+        assert_eq!(results[0].name, "lib::test_loop");
+        assert_eq!(results[0].status, TestCaseStatus::StepLimitExceeded);
+        assert!(results[0].resources.is_none());
+        assert_eq!(results[1].name, "lib::test_pass");
+        assert_eq!(results[1].status, TestCaseStatus::Ok);
+        assert_eq!(output.failed(), ["lib::test_loop"]);
+        assert!(
+            output
+                .notes()
+                .contains("test lib::test_loop ... step limit exceeded")
+        );
+    }
+
+    #[test]
+    fn test_profile() {
+        let code = r#"
+    fn sum_to(n: felt252) -> felt252 {
+        if n == 0 {
+            0
+        } else {
+            n + sum_to(n - 1)
+        }
+    }
+
+    #[test]
+    fn test_sum_to() {
+        assert(sum_to(3) == 6, 'wrong sum');
+    }
+    "#;
+        let output = run_cairo_tests(
+            code.to_string(),
+            &TestsConfig {
+                profile: Some(ProfilerKind::Cairo),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let profile = output.results()[0].profile.as_ref().unwrap();
+        assert!(
+            profile
+                .functions
+                .iter()
+                .any(|f| f.function == "lib::sum_to")
+        );
+        assert!(profile.collapsed_stacks.contains("lib::test_sum_to"));
+    }
+
+    #[test]
+    fn test_compilation_cached() {
+        let code = r#"
+    #[test]
+    fn test_cached() {
+        let unused = 1;
+    }
+
+    #[test]
+    fn test_other() {}
+        "#;
+        let run = |filter: &str| {
+            let config = TestsConfig {
+                filter: filter.to_string(),
+                ..Default::default()
+            };
+            run_cairo_tests(code.to_string(), &config).unwrap()
+        };
+        let hits = tests_cache_stats().hits;
+        assert_eq!(run("").passed().len(), 2);
+        let output = run("test_cached");
+        assert_eq!(output.passed(), ["lib::test_cached"]);
+        assert_eq!(output.diagnostics().len(), 1);
+        assert!(tests_cache_stats().hits > hits);
+    }
 }
@@ -0,0 +1,209 @@
+use base64::{Engine, engine::general_purpose::STANDARD};
+use cairo_runner_types::{
+    ExecutionTrace, MemoryCell, MemoryDump, MemoryRange, TraceEntry, TraceFormat,
+};
+use cairo_vm::vm::trace::trace_entry::RelocatedTraceEntry;
+use starknet_types_core::felt::Felt as Felt252;
+
+use crate::decode::felt_to_string;
+
+/// The execution trace of a run in `format`, from its relocated trace and memory.
+pub fn execution_trace(
+    format: TraceFormat,
+    trace: &[RelocatedTraceEntry],
+    memory: &[Option<Felt252>],
+) -> ExecutionTrace {
+    match format {
+        TraceFormat::Json => ExecutionTrace::Json {
+            trace: trace
+                .iter()
+                .map(|entry| TraceEntry {
+                    pc: entry.pc,
+                    ap: entry.ap,
+                    fp: entry.fp,
+                })
+                .collect(),
+            memory: memory
+                .iter()
+                .enumerate()
+                .filter_map(|(address, value)| {
+                    value.as_ref().map(|value| MemoryCell {
+                        address,
+                        value: felt_to_string(value),
+                    })
+                })
+                .collect(),
+        },
+        TraceFormat::Binary => ExecutionTrace::Binary {
+            trace: STANDARD.encode(encode_trace(trace)),
+            memory: STANDARD.encode(encode_memory(memory)),
+        },
+    }
+}
+
+/// The cells of `range` in the relocated `memory` of a run, whose segments start at the addresses
+/// `segments`, followed by the end of the last one. Missing segments have no cells.
+pub fn memory_dump(
+    range: &MemoryRange,
+    memory: &[Option<Felt252>],
+    segments: &[usize],
+) -> MemoryDump {
+    let (base, end) = match range.segment {
+        Some(segment) => {
+            let base = segments.get(segment).copied().unwrap_or(memory.len());
+            let end = segments
+                .get(segment.saturating_add(1))
+                .copied()
+                .unwrap_or(base);
+            (base, end)
+        }
+        None => (0, memory.len()),
+    };
+    // The last cells of a segment may never be written, and are not in the memory.
+    let end = end.min(memory.len());
+    let address = base.saturating_add(range.start).min(end);
+    let end = range
+        .length
+        .map_or(end, |length| end.min(address.saturating_add(length)));
+    MemoryDump {
+        segment: range.segment,
+        start: range.start,
+        address,
+        cells: memory[address..end]
+            .iter()
+            .map(|value| value.as_ref().map(felt_to_string))
+            .collect(),
+    }
+}
+
+/// Encodes a relocated trace like `cairo-run --trace_file`: the `ap`, `fp` and `pc` of each step,
+/// as 8 bytes little endian.
+pub fn encode_trace(trace: &[RelocatedTraceEntry]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(trace.len() * 24);
+    for entry in trace {
+        for register in [entry.ap, entry.fp, entry.pc] {
+            bytes.extend((register as u64).to_le_bytes());
+        }
+    }
+    bytes
+}
+
+/// Encodes a relocated memory like `cairo-run --memory_file`: the address of each written cell as
+/// 8 bytes, followed by its value as 32 bytes, both little endian.
+pub fn encode_memory(memory: &[Option<Felt252>]) -> Vec<u8> {
+    let mut bytes = vec![];
+    for (address, value) in memory.iter().enumerate() {
+        if let Some(value) = value {
+            bytes.extend((address as u64).to_le_bytes());
+            bytes.extend(value.to_bytes_le());
+        }
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod trace_tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_trace() {
+        let trace = [RelocatedTraceEntry {
+            pc: 1,
+            ap: 2,
+            fp: 3,
+        }];
+        let bytes = encode_trace(&trace);
+        assert_eq!(bytes.len(), 24);
+        assert_eq!(bytes[0..8], 2u64.to_le_bytes());
+        assert_eq!(bytes[8..16], 3u64.to_le_bytes());
+        assert_eq!(bytes[16..24], 1u64.to_le_bytes());
+    }
+
+    #[test]
+    fn test_encode_memory() {
+        let memory = [None, Some(Felt252::from(5)), None, Some(Felt252::from(-1))];
+        let bytes = encode_memory(&memory);
+        assert_eq!(bytes.len(), 2 * 40);
+        assert_eq!(bytes[0..8], 1u64.to_le_bytes());
+        assert_eq!(bytes[8], 5);
+        assert!(bytes[9..40].iter().all(|byte| *byte == 0));
+        assert_eq!(bytes[40..48], 3u64.to_le_bytes());
+        assert_eq!(bytes[48..80], Felt252::from(-1).to_bytes_le());
+    }
+
+    #[test]
+    fn test_memory_dump() {
+        let memory = [
+            None,
+            Some(Felt252::from(1)),
+            Some(Felt252::from(2)),
+            None,
+            Some(Felt252::from(4)),
+        ];
+        // The segments are [1, 3) and [3, 6).
+        let segments = [1, 3, 6];
+        let dump = |segment, start, length| {
+            memory_dump(
+                &MemoryRange {
+                    segment,
+                    start,
+                    length,
+                },
+                &memory,
+                &segments,
+            )
+        };
+
+        let whole = dump(None, 0, None);
+        assert_eq!(whole.address, 0);
+        assert_eq!(
+            whole.cells,
+            vec![
+                None,
+                Some("1".into()),
+                Some("2".into()),
+                None,
+                Some("4".into())
+            ]
+        );
+
+        let second = dump(Some(1), 1, Some(10));
+        assert_eq!(second.address, 4);
+        assert_eq!(second.cells, vec![Some("4".into())]);
+
+        assert_eq!(dump(Some(0), 1, Some(5)).cells, vec![Some("2".into())]);
+        assert!(dump(Some(2), 0, None).cells.is_empty());
+        assert!(dump(Some(7), 0, None).cells.is_empty());
+        assert!(dump(None, 9, None).cells.is_empty());
+    }
+
+    #[test]
+    fn test_execution_trace_json() {
+        let trace = [RelocatedTraceEntry {
+            pc: 1,
+            ap: 2,
+            fp: 3,
+        }];
+        let memory = [None, Some(Felt252::from(7))];
+        let ExecutionTrace::Json { trace, memory } =
+            execution_trace(TraceFormat::Json, &trace, &memory)
+        else {
+            panic!("Expected a JSON trace");
+        };
+        assert_eq!(
+            trace,
+            vec![TraceEntry {
+                pc: 1,
+                ap: 2,
+                fp: 3
+            }]
+        );
+        assert_eq!(
+            memory,
+            vec![MemoryCell {
+                address: 1,
+                value: "7".into()
+            }]
+        );
+    }
+}
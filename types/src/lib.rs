@@ -1,13 +1,491 @@
+use std::{collections::BTreeMap, fmt};
+
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Default)]
 pub struct CairoRunRequest {
+    /// The contents of `lib.cairo`.
+    #[serde(default)]
     pub code: String,
+    /// Additional crate files keyed by their relative path, e.g. `foo/bar.cairo`.
+    pub files: Option<BTreeMap<String, String>>,
+    /// The arguments of `main`: felts, or typed values such as u256 scalars, arrays and strings.
+    pub args: Option<Vec<serde_json::Value>>,
+    /// The function to run, e.g. `lib::fib` or `::compute`. Defaults to `::main`. For a session's
+    /// `/invoke` and `/call`, the ABI name of the contract's entrypoint, e.g. `increase`.
+    pub entrypoint: Option<String>,
+    /// The gas available to the run, capped by the server. Unmetered when unset.
+    pub available_gas: Option<usize>,
+    /// Profile the run, or each test, by Sierra or Cairo functions.
+    pub profile: Option<ProfilerKind>,
+    /// Return the execution trace and memory of the run, as JSON or binary.
+    pub trace: Option<TraceFormat>,
+    /// Return these cells of the memory the run, or each test, ended with.
+    pub memory: Option<MemoryRange>,
     pub test: Option<bool>,
+    /// Only run the tests whose full path contains this string.
+    pub filter: Option<String>,
+    /// Also run the `#[ignore]`d tests.
+    pub include_ignored: Option<bool>,
+    /// Only run the `#[ignore]`d tests.
+    pub ignored_only: Option<bool>,
+    /// Run the tests with gas accounting, reporting the gas usage of each test.
+    pub gas_enabled: Option<bool>,
+    /// The number of tests run in parallel, capped by the server.
+    pub jobs: Option<usize>,
+    /// The artifacts a `/compile` request returns.
+    pub artifacts: Option<Vec<ArtifactKind>>,
+    /// The maximal line length of `/format`, defaults to 100.
+    pub max_line_length: Option<usize>,
+    /// The indentation of `/format`, defaults to 4.
+    pub tab_size: Option<usize>,
+    /// Whether `/format` sorts the `use`s and `mod`s of each module, defaults to `true`.
+    pub sort_module_level_items: Option<bool>,
+    /// Whether `/format` merges the `use`s of the same path, defaults to `true`.
+    pub merge_use_items: Option<bool>,
+    /// Whether `/format` returns a unified diff instead of the formatted code.
+    pub diff: Option<bool>,
+    /// The contract a session's `/declare` or `/deploy` is about: its module path, e.g.
+    /// `lib::counter`, or its class hash.
+    pub contract: Option<String>,
+    /// The felts passed to the constructor of a session's `/deploy`, or to the entrypoint of its
+    /// `/invoke` and `/call`.
+    pub calldata: Option<Vec<serde_json::Value>>,
+    /// The salt of the address of a session's `/deploy`. Defaults to the number of contracts the
+    /// session deployed.
+    pub salt: Option<String>,
+    /// The deployed contract a session's `/invoke`, `/call` or `/storage` is about.
+    pub contract_address: Option<String>,
+    /// The storage address a session's `/storage` reads: a felt, or the name of a storage
+    /// variable, whose address is the `sn_keccak` of its name.
+    pub storage_key: Option<String>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Default)]
 pub struct CairoRunResponse {
     pub message: String,
     pub success: bool,
+    /// Compiler errors and warnings for the submitted code.
+    pub diagnostics: Vec<Diagnostic>,
+    /// What the program printed, e.g. with `println!`. For tests, the output of every test in order.
+    pub stdout: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<RunOutcome>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tests: Option<Vec<TestCaseResult>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub functions: Option<Vec<FunctionSignature>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artifacts: Option<CompiledArtifacts>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub formatted: Option<FormattedCode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session: Option<Session>,
+}
+
+/// The result of formatting code.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FormattedCode {
+    /// Whether formatting changed the code.
+    pub changed: bool,
+    /// The formatted code, unless a diff was requested.
+    pub code: Option<String>,
+    /// The unified diff from the submitted code to the formatted code, if requested.
+    pub diff: Option<String>,
+}
+
+/// An artifact of a compilation.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ArtifactKind {
+    /// The Sierra program, as text.
+    Sierra,
+    /// The Sierra program with its debug names, as JSON.
+    SierraJson,
+    /// The CASM of the Sierra program, as text.
+    Casm,
+    /// The contract class and compiled class of each `#[starknet::contract]` module.
+    Contracts,
+}
+
+/// The artifacts of a compilation, each `None` unless requested.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct CompiledArtifacts {
+    pub sierra: Option<String>,
+    /// A versioned Sierra program, the format of `cairo-compile --output`'s JSON.
+    pub sierra_json: Option<serde_json::Value>,
+    pub casm: Option<String>,
+    pub contracts: Option<Vec<ContractArtifacts>>,
+    /// Compiler warnings, moved into [`CairoRunResponse::diagnostics`] by the servers.
+    #[serde(skip)]
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// The artifacts of a `#[starknet::contract]` module, ready to be declared.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ContractArtifacts {
+    /// The path of the contract module, e.g. `lib::counter`.
+    pub name: String,
+    /// The Sierra contract class, the `.contract_class.json` of Scarb.
+    pub contract_class: serde_json::Value,
+    /// The CASM compiled class, the `.compiled_contract_class.json` of Scarb.
+    pub compiled_class: serde_json::Value,
+    /// The hash of the compiled class, as a hex string.
+    pub compiled_class_hash: String,
+}
+
+/// A Starknet state kept between the requests of the `/sessions` endpoints.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    /// The id of the session, e.g. in `/sessions/{id}/deploy`.
+    pub id: String,
+    /// The seconds the session is kept without requests.
+    pub ttl: u64,
+    /// The `#[starknet::contract]` modules of the session's code.
+    pub contracts: Vec<SessionContract>,
+}
+
+/// A contract of a [`Session`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SessionContract {
+    /// The path of the contract module, e.g. `lib::counter`.
+    pub name: String,
+    /// The class hash of the contract, as a hex string.
+    pub class_hash: String,
+    /// Whether the class was declared, which it must be to be deployed.
+    pub declared: bool,
+    /// The addresses the class was deployed at, as hex strings.
+    pub addresses: Vec<String>,
+}
+
+/// How a run of a Cairo function ended.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    Success,
+    Panic,
+    /// The run exhausted its `available_gas`.
+    OutOfGas,
+    /// The run did not finish before the server's deadline.
+    Timeout,
+    /// The run reached the server's maximum number of CASM steps.
+    StepLimitExceeded,
+}
+
+/// A single decoded item of panic data.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PanicItem {
+    /// A `ByteArray` serialized into the panic data.
+    String { value: String },
+    /// A plain felt as a hex string, with its short string interpretation if it has one.
+    Felt {
+        value: String,
+        short_string: Option<String>,
+    },
+}
+
+/// The structured result of running a Cairo function.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RunOutcome {
+    pub status: RunStatus,
+    /// The returned felts, as decimal strings.
+    pub values: Vec<String>,
+    /// The returned value decoded by its Cairo type, e.g. `{"x": 1, "y": 2}` for a struct, or
+    /// `None` if the function returns nothing or a type that is not decoded.
+    pub value: Option<serde_json::Value>,
+    /// The decoded panic data, empty unless the run panicked.
+    pub panic_data: Vec<PanicItem>,
+    /// Where the run panicked, innermost frame first, empty unless it panicked.
+    pub backtrace: Vec<BacktraceFrame>,
+    /// The gas consumed by the run, if it was metered.
+    pub gas_used: Option<u64>,
+    /// The number of CASM steps executed.
+    pub steps: usize,
+    /// The profile of the run, if requested.
+    pub profile: Option<Profile>,
+    /// The execution trace of the run, if requested.
+    pub trace: Option<ExecutionTrace>,
+    /// The requested cells of the memory the run ended with.
+    pub memory: Option<MemoryDump>,
+    /// What the run printed, moved into [`CairoRunResponse::stdout`] by the servers.
+    #[serde(skip)]
+    pub stdout: String,
+    /// Compiler warnings, moved into [`CairoRunResponse::diagnostics`] by the servers.
+    #[serde(skip)]
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl RunOutcome {
+    /// The outcome of a run that ended with `status` before returning, with no values, panic data
+    /// or resources.
+    pub fn failed(status: RunStatus, stdout: String, diagnostics: Vec<Diagnostic>) -> Self {
+        Self {
+            status,
+            values: vec![],
+            value: None,
+            panic_data: vec![],
+            backtrace: vec![],
+            gas_used: None,
+            steps: 0,
+            profile: None,
+            trace: None,
+            memory: None,
+            stdout,
+            diagnostics,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DiagnosticSeverity {
+    Error,
+    Warning,
+}
+
+/// A compiler diagnostic located in the user's sources.
+/// Lines and columns are 0-based.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: DiagnosticSeverity,
+    pub message: String,
+    /// The file the diagnostic points at, e.g. `lib.cairo`.
+    pub file: String,
+    /// The (line, column) of the start of the span.
+    pub start: (usize, usize),
+    /// The (line, column) of the end of the span.
+    pub end: (usize, usize),
+    /// The error code, e.g. `E0001`, if the diagnostic has one.
+    pub code: Option<String>,
+}
+
+/// A frame of a panic backtrace.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BacktraceFrame {
+    /// The Cairo function of the frame, e.g. `lib::main` or `core::panic_with_felt252`.
+    pub function: String,
+    /// Where the function panicked or called the next frame, if it is known.
+    pub location: Option<SourceLocation>,
+}
+
+/// A position in the sources, or in the corelib for files under `core/`.
+/// Lines and columns are 0-based.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    /// The file of the position, e.g. `lib.cairo`.
+    pub file: String,
+    pub line: usize,
+    pub col: usize,
+}
+
+/// The status of a single test case.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TestCaseStatus {
+    Ok,
+    Fail,
+    Ignored,
+    /// The test reached the server's maximum number of CASM steps.
+    StepLimitExceeded,
+}
+
+/// The structured result of a single test case.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TestCaseResult {
+    /// The full path of the test function, e.g. `lib::test_pass`.
+    pub name: String,
+    pub status: TestCaseStatus,
+    /// The estimated gas usage of the test, if relevant.
+    pub gas_usage: Option<i64>,
+    /// The decoded panic data, if the test panicked.
+    pub panic_data: Option<Vec<PanicItem>>,
+    /// Where the test panicked, innermost frame first, if it panicked.
+    pub backtrace: Option<Vec<BacktraceFrame>>,
+    /// The panic data the test expects, if it has `#[should_panic(expected: ...)]`.
+    pub expected_panic_data: Option<Vec<PanicItem>>,
+    /// Why the test failed, if it did.
+    pub failure_reason: Option<String>,
+    /// What the test printed.
+    pub stdout: String,
+    /// The resources the test used, if it ran.
+    pub resources: Option<ResourceUsage>,
+    /// The profile of the test, if requested and it ran.
+    pub profile: Option<Profile>,
+    /// The requested cells of the memory the test ended with, if it ran.
+    pub memory: Option<MemoryDump>,
+}
+
+/// What a profile attributes the steps of a run to.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProfilerKind {
+    /// Sierra functions, including the ones generated by the compiler.
+    Sierra,
+    /// Cairo functions, leaving out the generated ones.
+    Cairo,
+}
+
+/// The CASM steps of a run, weighted by function, statement and call stack.
+/// Weights are sorted from the heaviest.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Profile {
+    /// The steps spent in each function.
+    pub functions: Vec<FunctionWeight>,
+    /// The steps spent in each Sierra statement.
+    pub statements: Vec<StatementWeight>,
+    /// One `caller;callee weight` line per call stack, the input of flamegraph tools.
+    pub collapsed_stacks: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FunctionWeight {
+    pub function: String,
+    pub weight: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct StatementWeight {
+    /// The index of the statement in the Sierra program.
+    pub index: usize,
+    /// The statement, e.g. `felt252_add([0], [1]) -> ([2])`.
+    pub statement: String,
+    pub weight: usize,
+}
+
+/// The format of the execution trace of a run.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TraceFormat {
+    /// The registers of each step and the memory cells, as JSON.
+    Json,
+    /// The `trace.bin` and `memory.bin` files of `cairo-run`, base64 encoded.
+    Binary,
+}
+
+/// The execution trace of a run and the memory it ended with, with relocated addresses.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "format", rename_all = "snake_case")]
+pub enum ExecutionTrace {
+    Json {
+        /// The registers before each step.
+        trace: Vec<TraceEntry>,
+        /// The memory cells that were written, by increasing address.
+        memory: Vec<MemoryCell>,
+    },
+    /// `trace` holds the `ap`, `fp` and `pc` of each step, and `memory` the address and value of
+    /// each written cell, as 8 byte addresses and 32 byte values in little endian.
+    Binary { trace: String, memory: String },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub pc: usize,
+    pub ap: usize,
+    pub fp: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MemoryCell {
+    pub address: usize,
+    /// The felt in the cell, as a decimal string.
+    pub value: String,
+}
+
+/// A range of the memory of a run.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryRange {
+    /// The segment of the range, e.g. 0 for the program and 1 for the execution segment. When
+    /// unset, the range is in the relocated memory, where the segments follow each other.
+    pub segment: Option<usize>,
+    /// The offset of the first cell in the segment, or its address in the relocated memory.
+    #[serde(default)]
+    pub start: usize,
+    /// The number of cells, up to the end of the segment or of the memory when unset.
+    pub length: Option<usize>,
+}
+
+/// The cells of a [`MemoryRange`], cut at the end of its segment.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MemoryDump {
+    pub segment: Option<usize>,
+    pub start: usize,
+    /// The address of the first cell in the relocated memory.
+    pub address: usize,
+    /// The felts in the cells as decimal strings, `None` for the cells that were never written.
+    pub cells: Vec<Option<String>>,
+}
+
+/// The resources used by a run.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResourceUsage {
+    /// The number of CASM steps executed.
+    pub steps: usize,
+    pub memory_holes: usize,
+    /// The instances used of each builtin, e.g. `range_check_builtin`.
+    pub builtins: BTreeMap<String, usize>,
+    /// The number of calls of each syscall, e.g. `CallContract`.
+    pub syscalls: BTreeMap<String, usize>,
+}
+
+/// The signature of a function that can be run, with implicit parameters omitted.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FunctionSignature {
+    /// The full path of the function, e.g. `lib::fib`.
+    pub name: String,
+    /// The parameter types, e.g. `core::felt252`.
+    pub params: Vec<String>,
+    /// The return types, without the panic wrapper.
+    pub returns: Vec<String>,
+    /// Whether the function can panic.
+    pub panics: bool,
+}
+
+impl fmt::Display for PanicItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PanicItem::String { value } => write!(f, "\"{value}\""),
+            PanicItem::Felt {
+                value,
+                short_string: Some(short_string),
+            } => write!(f, "{value} ('{short_string}')"),
+            PanicItem::Felt { value, .. } => write!(f, "{value}"),
+        }
+    }
+}
+
+impl fmt::Display for BacktraceFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.function)?;
+        if let Some(SourceLocation { file, line, col }) = &self.location {
+            write!(f, " ({file}:{}:{})", line + 1, col + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for RunOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.status {
+            RunStatus::Success => match &self.value {
+                Some(value) => writeln!(f, "Run completed successfully, returning {value}"),
+                None => writeln!(
+                    f,
+                    "Run completed successfully, returning [{}]",
+                    self.values.join(", ")
+                ),
+            },
+            RunStatus::Panic => {
+                let items: Vec<String> = self.panic_data.iter().map(|i| i.to_string()).collect();
+                writeln!(f, "Run panicked with [{}].", items.join(", "))?;
+                for frame in &self.backtrace {
+                    writeln!(f, "    at {frame}")?;
+                }
+                Ok(())
+            }
+            RunStatus::OutOfGas => writeln!(f, "Run failed: out of gas."),
+            RunStatus::Timeout => writeln!(f, "Run failed: timed out."),
+            RunStatus::StepLimitExceeded => writeln!(f, "Run failed: step limit exceeded."),
+        }
+    }
 }